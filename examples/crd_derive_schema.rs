@@ -10,6 +10,7 @@ use kube::{
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 // This example shows how the generated schema affects defaulting and validation.
 // The integration test `crd_schema_test` in `kube-derive` contains the full CRD JSON generated from this struct.
@@ -118,7 +119,8 @@ async fn main() -> Result<()> {
     println!("Creating CRD v1");
     let client = Client::try_default().await?;
     delete_crd(client.clone()).await?;
-    assert!(create_crd(client.clone()).await.is_ok());
+    kube_runtime::crd::install::<Foo>(client.clone(), "crd_derive_schema", Duration::from_secs(15), None)
+        .await?;
 
     // Test creating Foo resource.
     let namespace = std::env::var("NAMESPACE").unwrap_or_else(|_| "default".into());
@@ -219,37 +221,6 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-// Create CRD and wait for it to be ready.
-async fn create_crd(client: Client) -> Result<CustomResourceDefinition> {
-    let api = Api::<CustomResourceDefinition>::all(client);
-    api.create(&PostParams::default(), &Foo::crd()).await?;
-
-    // Wait until ready
-    let timeout_secs = 15;
-    let lp = ListParams::default()
-        .fields("metadata.name=foos.clux.dev")
-        .timeout(timeout_secs);
-    let mut stream = api.watch(&lp, "0").await?.boxed_local();
-    while let Some(status) = stream.try_next().await? {
-        if let WatchEvent::Modified(crd) = status {
-            let accepted = crd
-                .status
-                .as_ref()
-                .map(|s| {
-                    s.conditions
-                        .iter()
-                        .any(|c| c.type_ == "NamesAccepted" && c.status == "True")
-                })
-                .unwrap_or(false);
-            if accepted {
-                return Ok(crd);
-            }
-        }
-    }
-
-    Err(anyhow!(format!("CRD not ready after {} seconds", timeout_secs)))
-}
-
 // Delete the CRD if it exists and wait until it's deleted.
 async fn delete_crd(client: Client) -> Result<()> {
     let api = Api::<CustomResourceDefinition>::all(client);
@@ -0,0 +1,759 @@
+//! Backwards compatibility checks between two versions of a `CustomResourceDefinition`
+//!
+//! Compares the `openAPIV3Schema` of every version of an already deployed crd against a new one
+//! (typically generated by `CustomResourceExt::crd`) and classifies each difference as breaking or safe.
+//!
+//! ```
+//! use kube_core::crd::compat;
+//! # use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+//! # fn check(deployed: &CustomResourceDefinition, generated: &CustomResourceDefinition) {
+//! let report = compat::compare(deployed, generated);
+//! for change in report.breaking() {
+//!     eprintln!("breaking: {}", change);
+//! }
+//! assert!(report.is_compatible());
+//! # }
+//! ```
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+    CustomResourceDefinition, JSONSchemaProps, JSONSchemaPropsOrArray, JSONSchemaPropsOrBool,
+};
+use std::fmt;
+
+/// A single difference found between two crd schemas
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaChange {
+    /// The crd version the change was found in
+    pub version: String,
+    /// Path to the changed property, e.g. `.spec.items[*].name`
+    ///
+    /// Empty for changes that affect the whole crd or version.
+    pub path: String,
+    /// What changed
+    pub change: Change,
+}
+
+impl SchemaChange {
+    /// Whether this change can break existing clients or stored objects
+    pub fn is_breaking(&self) -> bool {
+        self.change.is_breaking()
+    }
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}: {}", self.version, self.change)
+        } else {
+            write!(f, "{} {}: {}", self.version, self.path, self.change)
+        }
+    }
+}
+
+/// Kinds of changes between two crd schemas
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The resource scope changed between `Namespaced` and `Cluster`
+    ScopeChanged {
+        /// Previous scope
+        old: String,
+        /// New scope
+        new: String,
+    },
+    /// A served version was removed or is no longer served
+    VersionRemoved,
+    /// A new version was added
+    VersionAdded,
+    /// A property was removed
+    FieldRemoved,
+    /// A new optional property was added
+    FieldAdded,
+    /// A new property was added and marked as required
+    RequiredFieldAdded,
+    /// An existing property became required
+    FieldNowRequired,
+    /// An existing property is no longer required
+    FieldNoLongerRequired,
+    /// The `type` or `format` of a property changed
+    TypeChanged {
+        /// Previous type (and format)
+        old: String,
+        /// New type (and format)
+        new: String,
+    },
+    /// A property is no longer nullable
+    NullableRemoved,
+    /// A property became nullable
+    NullableAdded,
+    /// Values were removed from an `enum`, or an `enum` was introduced
+    EnumNarrowed {
+        /// Values that are no longer accepted
+        removed: Vec<serde_json::Value>,
+    },
+    /// Values were added to an `enum`, or an `enum` was removed
+    EnumWidened {
+        /// Values that are now also accepted
+        added: Vec<serde_json::Value>,
+    },
+    /// A validation constraint (like `maximum` or `maxLength`) became stricter
+    ConstraintNarrowed {
+        /// The name of the constraint
+        constraint: &'static str,
+    },
+    /// A validation constraint (like `maximum` or `maxLength`) was relaxed or removed
+    ConstraintWidened {
+        /// The name of the constraint
+        constraint: &'static str,
+    },
+    /// `x-kubernetes-list-type` or `x-kubernetes-list-map-keys` changed
+    ListSemanticsChanged {
+        /// Previous list type and keys
+        old: String,
+        /// New list type and keys
+        new: String,
+    },
+    /// `x-kubernetes-map-type` changed
+    MapSemanticsChanged {
+        /// Previous map type
+        old: String,
+        /// New map type
+        new: String,
+    },
+    /// `x-kubernetes-preserve-unknown-fields` was removed, so unknown fields are now pruned
+    PreserveUnknownFieldsRemoved,
+    /// `x-kubernetes-preserve-unknown-fields` was added
+    PreserveUnknownFieldsAdded,
+    /// `items` switched between a single schema and a list of schemas
+    ItemsChanged {
+        /// Previous shape of `items`
+        old: String,
+        /// New shape of `items`
+        new: String,
+    },
+}
+
+impl Change {
+    /// Whether this change can break existing clients or stored objects
+    pub fn is_breaking(&self) -> bool {
+        !matches!(
+            self,
+            Change::VersionAdded
+                | Change::FieldAdded
+                | Change::FieldNoLongerRequired
+                | Change::NullableAdded
+                | Change::EnumWidened { .. }
+                | Change::ConstraintWidened { .. }
+                | Change::PreserveUnknownFieldsAdded
+        )
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::ScopeChanged { old, new } => write!(f, "scope changed from {} to {}", old, new),
+            Change::VersionRemoved => write!(f, "version removed"),
+            Change::VersionAdded => write!(f, "version added"),
+            Change::FieldRemoved => write!(f, "field removed"),
+            Change::FieldAdded => write!(f, "field added"),
+            Change::RequiredFieldAdded => write!(f, "required field added"),
+            Change::FieldNowRequired => write!(f, "field is now required"),
+            Change::FieldNoLongerRequired => write!(f, "field is no longer required"),
+            Change::TypeChanged { old, new } => write!(f, "type changed from {} to {}", old, new),
+            Change::NullableRemoved => write!(f, "field is no longer nullable"),
+            Change::NullableAdded => write!(f, "field is now nullable"),
+            Change::EnumNarrowed { removed } => write!(f, "enum no longer accepts {:?}", removed),
+            Change::EnumWidened { added } => write!(f, "enum now also accepts {:?}", added),
+            Change::ConstraintNarrowed { constraint } => write!(f, "{} is stricter", constraint),
+            Change::ConstraintWidened { constraint } => write!(f, "{} is relaxed", constraint),
+            Change::ListSemanticsChanged { old, new } => {
+                write!(f, "list semantics changed from {} to {}", old, new)
+            }
+            Change::MapSemanticsChanged { old, new } => {
+                write!(f, "map semantics changed from {} to {}", old, new)
+            }
+            Change::PreserveUnknownFieldsRemoved => write!(f, "unknown fields are no longer preserved"),
+            Change::PreserveUnknownFieldsAdded => write!(f, "unknown fields are now preserved"),
+            Change::ItemsChanged { old, new } => write!(f, "items changed from {} to {}", old, new),
+        }
+    }
+}
+
+/// The result of comparing two crds with [`compare`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Compatibility {
+    /// All changes found, breaking and safe
+    pub changes: Vec<SchemaChange>,
+}
+
+impl Compatibility {
+    /// True if no breaking changes were found
+    pub fn is_compatible(&self) -> bool {
+        self.changes.iter().all(|c| !c.is_breaking())
+    }
+
+    /// Iterate over breaking changes
+    pub fn breaking(&self) -> impl Iterator<Item = &SchemaChange> {
+        self.changes.iter().filter(|c| c.is_breaking())
+    }
+
+    /// Iterate over safe changes
+    pub fn safe(&self) -> impl Iterator<Item = &SchemaChange> {
+        self.changes.iter().filter(|c| !c.is_breaking())
+    }
+}
+
+impl fmt::Display for Compatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            let marker = if change.is_breaking() { "BREAKING" } else { "safe" };
+            writeln!(f, "{}: {}", marker, change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare a deployed crd against a new one and report the differences
+///
+/// Versions are matched by name. Versions that are no longer served are ignored on the old side.
+pub fn compare(old: &CustomResourceDefinition, new: &CustomResourceDefinition) -> Compatibility {
+    let mut report = Compatibility::default();
+    if old.spec.scope != new.spec.scope {
+        report.changes.push(SchemaChange {
+            version: String::new(),
+            path: String::new(),
+            change: Change::ScopeChanged {
+                old: old.spec.scope.clone(),
+                new: new.spec.scope.clone(),
+            },
+        });
+    }
+    for old_ver in old.spec.versions.iter().filter(|v| v.served) {
+        let new_ver = new
+            .spec
+            .versions
+            .iter()
+            .find(|v| v.name == old_ver.name && v.served);
+        let new_ver = match new_ver {
+            Some(v) => v,
+            None => {
+                report.changes.push(SchemaChange {
+                    version: old_ver.name.clone(),
+                    path: String::new(),
+                    change: Change::VersionRemoved,
+                });
+                continue;
+            }
+        };
+        let old_schema = old_ver
+            .schema
+            .as_ref()
+            .and_then(|s| s.open_api_v3_schema.as_ref());
+        let new_schema = new_ver
+            .schema
+            .as_ref()
+            .and_then(|s| s.open_api_v3_schema.as_ref());
+        if let (Some(old_schema), Some(new_schema)) = (old_schema, new_schema) {
+            let mut diff = Diff {
+                version: &old_ver.name,
+                changes: &mut report.changes,
+            };
+            diff.schema("", old_schema, new_schema);
+        }
+    }
+    for new_ver in &new.spec.versions {
+        if new_ver.served
+            && !old
+                .spec
+                .versions
+                .iter()
+                .any(|v| v.name == new_ver.name && v.served)
+        {
+            report.changes.push(SchemaChange {
+                version: new_ver.name.clone(),
+                path: String::new(),
+                change: Change::VersionAdded,
+            });
+        }
+    }
+    report
+}
+
+struct Diff<'a> {
+    version: &'a str,
+    changes: &'a mut Vec<SchemaChange>,
+}
+
+impl Diff<'_> {
+    fn push(&mut self, path: &str, change: Change) {
+        self.changes.push(SchemaChange {
+            version: self.version.to_string(),
+            path: path.to_string(),
+            change,
+        });
+    }
+
+    fn schema(&mut self, path: &str, old: &JSONSchemaProps, new: &JSONSchemaProps) {
+        let (old, new) = (unwrap_all_of(old), unwrap_all_of(new));
+
+        let (old_type, new_type) = (type_name(old), type_name(new));
+        if old_type != new_type {
+            // a type switch makes any nested comparison meaningless
+            self.push(path, Change::TypeChanged {
+                old: old_type,
+                new: new_type,
+            });
+            return;
+        }
+
+        match (old.nullable.unwrap_or(false), new.nullable.unwrap_or(false)) {
+            (true, false) => self.push(path, Change::NullableRemoved),
+            (false, true) => self.push(path, Change::NullableAdded),
+            _ => {}
+        }
+
+        self.enums(path, old, new);
+        self.constraints(path, old, new);
+        self.list_semantics(path, old, new);
+        self.map_semantics(path, old, new);
+        match (
+            old.x_kubernetes_preserve_unknown_fields.unwrap_or(false),
+            new.x_kubernetes_preserve_unknown_fields.unwrap_or(false),
+        ) {
+            (true, false) => self.push(path, Change::PreserveUnknownFieldsRemoved),
+            (false, true) => self.push(path, Change::PreserveUnknownFieldsAdded),
+            _ => {}
+        }
+
+        // object properties
+        for (name, old_prop) in &old.properties {
+            let prop_path = format!("{}.{}", path, name);
+            match new.properties.get(name) {
+                Some(new_prop) => {
+                    let (was_required, is_required) =
+                        (old.required.contains(name), new.required.contains(name));
+                    if !was_required && is_required {
+                        self.push(&prop_path, Change::FieldNowRequired);
+                    } else if was_required && !is_required {
+                        self.push(&prop_path, Change::FieldNoLongerRequired);
+                    }
+                    self.schema(&prop_path, old_prop, new_prop);
+                }
+                None => {
+                    // properties of objects that preserve unknown fields are still accepted
+                    if new.x_kubernetes_preserve_unknown_fields != Some(true) {
+                        self.push(&prop_path, Change::FieldRemoved);
+                    }
+                }
+            }
+        }
+        for name in new.properties.keys().filter(|n| !old.properties.contains_key(*n)) {
+            let prop_path = format!("{}.{}", path, name);
+            if new.required.contains(name) {
+                self.push(&prop_path, Change::RequiredFieldAdded);
+            } else {
+                self.push(&prop_path, Change::FieldAdded);
+            }
+        }
+
+        // array items
+        match (&old.items, &new.items) {
+            (
+                Some(JSONSchemaPropsOrArray::Schema(old_items)),
+                Some(JSONSchemaPropsOrArray::Schema(new_items)),
+            ) => {
+                self.schema(&format!("{}[*]", path), old_items, new_items);
+            }
+            (
+                Some(JSONSchemaPropsOrArray::Schemas(old_items)),
+                Some(JSONSchemaPropsOrArray::Schemas(new_items)),
+            ) => {
+                if old_items.len() != new_items.len() {
+                    self.push(path, Change::ItemsChanged {
+                        old: items_shape(&old.items),
+                        new: items_shape(&new.items),
+                    });
+                }
+                for (i, (old_item, new_item)) in old_items.iter().zip(new_items).enumerate() {
+                    self.schema(&format!("{}[{}]", path, i), old_item, new_item);
+                }
+            }
+            (Some(_), Some(_)) => self.push(path, Change::ItemsChanged {
+                old: items_shape(&old.items),
+                new: items_shape(&new.items),
+            }),
+            _ => {}
+        }
+
+        // map values
+        if let (
+            Some(JSONSchemaPropsOrBool::Schema(old_values)),
+            Some(JSONSchemaPropsOrBool::Schema(new_values)),
+        ) = (&old.additional_properties, &new.additional_properties)
+        {
+            self.schema(&format!("{}[*]", path), old_values, new_values);
+        }
+    }
+
+    fn enums(&mut self, path: &str, old: &JSONSchemaProps, new: &JSONSchemaProps) {
+        let removed: Vec<_> = old
+            .enum_
+            .iter()
+            .filter(|v| !new.enum_.contains(v))
+            .map(|v| v.0.clone())
+            .collect();
+        let added: Vec<_> = new
+            .enum_
+            .iter()
+            .filter(|v| !old.enum_.contains(v))
+            .map(|v| v.0.clone())
+            .collect();
+        if old.enum_.is_empty() && !new.enum_.is_empty() {
+            // previously any value was accepted
+            self.push(path, Change::EnumNarrowed { removed: vec![] });
+        } else if !old.enum_.is_empty() && new.enum_.is_empty() {
+            self.push(path, Change::EnumWidened { added: vec![] });
+        } else {
+            if !removed.is_empty() {
+                self.push(path, Change::EnumNarrowed { removed });
+            }
+            if !added.is_empty() {
+                self.push(path, Change::EnumWidened { added });
+            }
+        }
+    }
+
+    fn constraints(&mut self, path: &str, old: &JSONSchemaProps, new: &JSONSchemaProps) {
+        let maxima = [
+            ("maximum", old.maximum, new.maximum),
+            (
+                "maxLength",
+                old.max_length.map(|v| v as f64),
+                new.max_length.map(|v| v as f64),
+            ),
+            (
+                "maxItems",
+                old.max_items.map(|v| v as f64),
+                new.max_items.map(|v| v as f64),
+            ),
+            (
+                "maxProperties",
+                old.max_properties.map(|v| v as f64),
+                new.max_properties.map(|v| v as f64),
+            ),
+        ];
+        for (constraint, old, new) in maxima.iter() {
+            match (old, new) {
+                (None, Some(_)) => self.push(path, Change::ConstraintNarrowed { constraint }),
+                (Some(_), None) => self.push(path, Change::ConstraintWidened { constraint }),
+                (Some(o), Some(n)) if n < o => self.push(path, Change::ConstraintNarrowed { constraint }),
+                (Some(o), Some(n)) if n > o => self.push(path, Change::ConstraintWidened { constraint }),
+                _ => {}
+            }
+        }
+        let minima = [
+            ("minimum", old.minimum, new.minimum),
+            (
+                "minLength",
+                old.min_length.map(|v| v as f64),
+                new.min_length.map(|v| v as f64),
+            ),
+            (
+                "minItems",
+                old.min_items.map(|v| v as f64),
+                new.min_items.map(|v| v as f64),
+            ),
+            (
+                "minProperties",
+                old.min_properties.map(|v| v as f64),
+                new.min_properties.map(|v| v as f64),
+            ),
+        ];
+        for (constraint, old, new) in minima.iter() {
+            match (old, new) {
+                (None, Some(_)) => self.push(path, Change::ConstraintNarrowed { constraint }),
+                (Some(_), None) => self.push(path, Change::ConstraintWidened { constraint }),
+                (Some(o), Some(n)) if n > o => self.push(path, Change::ConstraintNarrowed { constraint }),
+                (Some(o), Some(n)) if n < o => self.push(path, Change::ConstraintWidened { constraint }),
+                _ => {}
+            }
+        }
+        match (&old.pattern, &new.pattern) {
+            (None, Some(_)) => self.push(path, Change::ConstraintNarrowed {
+                constraint: "pattern",
+            }),
+            (Some(_), None) => self.push(path, Change::ConstraintWidened {
+                constraint: "pattern",
+            }),
+            // we cannot compare regexes, so assume the worst
+            (Some(o), Some(n)) if o != n => self.push(path, Change::ConstraintNarrowed {
+                constraint: "pattern",
+            }),
+            _ => {}
+        }
+    }
+
+    fn list_semantics(&mut self, path: &str, old: &JSONSchemaProps, new: &JSONSchemaProps) {
+        if old.x_kubernetes_list_type != new.x_kubernetes_list_type
+            || old.x_kubernetes_list_map_keys != new.x_kubernetes_list_map_keys
+        {
+            self.push(path, Change::ListSemanticsChanged {
+                old: list_semantics(old),
+                new: list_semantics(new),
+            });
+        }
+    }
+
+    fn map_semantics(&mut self, path: &str, old: &JSONSchemaProps, new: &JSONSchemaProps) {
+        let map_type = |schema: &JSONSchemaProps| {
+            schema
+                .x_kubernetes_map_type
+                .clone()
+                .unwrap_or_else(|| "granular".to_string())
+        };
+        let (old, new) = (map_type(old), map_type(new));
+        if old != new {
+            self.push(path, Change::MapSemanticsChanged { old, new });
+        }
+    }
+}
+
+/// Schemars wraps documented struct fields in a single element `allOf`
+fn unwrap_all_of(schema: &JSONSchemaProps) -> &JSONSchemaProps {
+    match schema.all_of.as_slice() {
+        [inner] if schema.type_.is_none() && schema.properties.is_empty() => unwrap_all_of(inner),
+        _ => schema,
+    }
+}
+
+fn type_name(schema: &JSONSchemaProps) -> String {
+    let ty = if schema.x_kubernetes_int_or_string == Some(true) {
+        "int-or-string"
+    } else {
+        schema.type_.as_deref().unwrap_or("any")
+    };
+    match &schema.format {
+        Some(format) => format!("{} ({})", ty, format),
+        None => ty.to_string(),
+    }
+}
+
+fn items_shape(items: &Option<JSONSchemaPropsOrArray>) -> String {
+    match items {
+        Some(JSONSchemaPropsOrArray::Schema(_)) => "a single schema".to_string(),
+        Some(JSONSchemaPropsOrArray::Schemas(items)) => format!("a list of {} schemas", items.len()),
+        None => "none".to_string(),
+    }
+}
+
+fn list_semantics(schema: &JSONSchemaProps) -> String {
+    let ty = schema.x_kubernetes_list_type.as_deref().unwrap_or("atomic");
+    if schema.x_kubernetes_list_map_keys.is_empty() {
+        ty.to_string()
+    } else {
+        format!("{} {:?}", ty, schema.x_kubernetes_list_map_keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn crd(versions: serde_json::Value) -> CustomResourceDefinition {
+        serde_json::from_value(json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": versions,
+            }
+        }))
+        .unwrap()
+    }
+
+    fn single(spec: serde_json::Value) -> CustomResourceDefinition {
+        crd(json!([{
+            "name": "v1",
+            "served": true,
+            "storage": true,
+            "schema": {
+                "openAPIV3Schema": {
+                    "type": "object",
+                    "properties": { "spec": spec },
+                    "required": ["spec"],
+                }
+            }
+        }]))
+    }
+
+    #[test]
+    fn identical_crds_are_compatible() {
+        let spec = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+            "required": ["name"],
+        });
+        let report = compare(&single(spec.clone()), &single(spec));
+        assert!(report.changes.is_empty());
+        assert!(report.is_compatible());
+    }
+
+    #[test]
+    fn additions_are_safe() {
+        let old = single(json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "mode": { "type": "string", "enum": ["a"] },
+            },
+            "required": ["name"],
+        }));
+        let new = single(json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "nullable": true },
+                "mode": { "type": "string", "enum": ["a", "b"] },
+                "extra": { "type": "integer" },
+            },
+        }));
+        let report = compare(&old, &new);
+        assert!(report.is_compatible(), "{}", report);
+        let changes: Vec<_> = report
+            .changes
+            .iter()
+            .map(|c| (c.path.as_str(), &c.change))
+            .collect();
+        assert!(changes.contains(&(".spec.name", &Change::FieldNoLongerRequired)));
+        assert!(changes.contains(&(".spec.name", &Change::NullableAdded)));
+        assert!(changes.contains(&(".spec.extra", &Change::FieldAdded)));
+        assert!(changes.contains(&(".spec.mode", &Change::EnumWidened {
+            added: vec![json!("b")]
+        })));
+    }
+
+    #[test]
+    fn removals_and_narrowing_are_breaking() {
+        let old = single(json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "count": { "type": "integer", "maximum": 10 },
+                "mode": { "type": "string", "enum": ["a", "b"] },
+                "items": {
+                    "type": "array",
+                    "items": { "type": "object", "properties": { "key": { "type": "string" } } },
+                },
+            },
+        }));
+        let new = single(json!({
+            "type": "object",
+            "properties": {
+                "count": { "type": "string" },
+                "mode": { "type": "string", "enum": ["a"] },
+                "items": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "map",
+                    "x-kubernetes-list-map-keys": ["key"],
+                    "items": {
+                        "type": "object",
+                        "properties": { "key": { "type": "string" } },
+                        "required": ["key"],
+                    },
+                },
+                "owner": { "type": "string" },
+            },
+            "required": ["owner"],
+        }));
+        let report = compare(&old, &new);
+        assert!(!report.is_compatible());
+        let breaking: Vec<_> = report.breaking().map(|c| (c.path.as_str(), &c.change)).collect();
+        assert_eq!(breaking, vec![
+            (".spec.count", &Change::TypeChanged {
+                old: "integer".into(),
+                new: "string".into()
+            }),
+            (".spec.items", &Change::ListSemanticsChanged {
+                old: "atomic".into(),
+                new: "map [\"key\"]".into()
+            }),
+            (".spec.items[*].key", &Change::FieldNowRequired),
+            (".spec.mode", &Change::EnumNarrowed {
+                removed: vec![json!("b")]
+            }),
+            (".spec.name", &Change::FieldRemoved),
+            (".spec.owner", &Change::RequiredFieldAdded),
+        ]);
+    }
+
+    #[test]
+    fn extension_and_items_changes_are_breaking() {
+        let old = single(json!({
+            "type": "object",
+            "x-kubernetes-preserve-unknown-fields": true,
+            "properties": {
+                "labels": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                },
+                "pair": { "type": "array", "items": { "type": "string" } },
+            },
+        }));
+        let new = single(json!({
+            "type": "object",
+            "properties": {
+                "labels": {
+                    "type": "object",
+                    "x-kubernetes-map-type": "atomic",
+                    "additionalProperties": { "type": "string" },
+                },
+                "pair": { "type": "array", "items": [{ "type": "string" }, { "type": "string" }] },
+            },
+        }));
+        let report = compare(&old, &new);
+        let breaking: Vec<_> = report.breaking().map(|c| (c.path.as_str(), &c.change)).collect();
+        assert_eq!(breaking, vec![
+            (".spec", &Change::PreserveUnknownFieldsRemoved),
+            (".spec.labels", &Change::MapSemanticsChanged {
+                old: "granular".into(),
+                new: "atomic".into()
+            }),
+            (".spec.pair", &Change::ItemsChanged {
+                old: "a single schema".into(),
+                new: "a list of 2 schemas".into()
+            }),
+        ]);
+        assert!(compare(&new, &old)
+            .changes
+            .iter()
+            .any(|c| c.change == Change::PreserveUnknownFieldsAdded && !c.is_breaking()));
+    }
+
+    #[test]
+    fn version_changes() {
+        let schema = json!({ "openAPIV3Schema": { "type": "object" } });
+        let old = crd(json!([
+            { "name": "v1alpha1", "served": true, "storage": false, "schema": schema },
+            { "name": "v1beta1", "served": true, "storage": true, "schema": schema },
+        ]));
+        let new = crd(json!([
+            { "name": "v1beta1", "served": true, "storage": false, "schema": schema },
+            { "name": "v1", "served": true, "storage": true, "schema": schema },
+        ]));
+        let report = compare(&old, &new);
+        assert_eq!(report.changes, vec![
+            SchemaChange {
+                version: "v1alpha1".into(),
+                path: "".into(),
+                change: Change::VersionRemoved
+            },
+            SchemaChange {
+                version: "v1".into(),
+                path: "".into(),
+                change: Change::VersionAdded
+            },
+        ]);
+    }
+}
@@ -2,6 +2,9 @@
 
 use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions as apiexts;
 
+pub mod compat;
+pub use compat::{compare, Change, Compatibility, SchemaChange};
+
 /// Types for v1 CustomResourceDefinitions
 pub mod v1 {
     /// Extension trait that will be implemented by kube-derive
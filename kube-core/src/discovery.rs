@@ -1,11 +1,12 @@
 //! Type information structs for API discovery
 use crate::{gvk::GroupVersionKind, resource::Resource};
+use serde::{Deserialize, Serialize};
 
 /// Information about a Kubernetes API resource
 ///
 /// Enough information to use it like a `Resource` by passing it to the dynamic `Api`
 /// constructors like `Api::all_with` and `Api::namespaced_with`.
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ApiResource {
     /// Resource group, empty for core group.
     pub group: String,
@@ -57,7 +58,7 @@ impl ApiResource {
 }
 
 /// Resource scope
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Scope {
     /// Objects are global
     Cluster,
@@ -86,7 +87,7 @@ pub mod verbs {
 }
 
 /// Contains the capabilities of an API resource
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiCapabilities {
     /// Scope of the resource
     pub scope: Scope,
@@ -1,6 +1,6 @@
-use darling::FromDeriveInput;
+use darling::{util::Override, FromDeriveInput, FromField};
 use proc_macro2::{Ident, Span};
-use syn::{Data, DeriveInput, Path};
+use syn::{Attribute, Data, DeriveInput, Fields, Lit, Meta, NestedMeta, Path};
 
 /// Values we can parse from #[kube(attrs)]
 #[derive(Debug, Default, FromDeriveInput)]
@@ -33,12 +33,197 @@ struct KubeAttrs {
     printcolums: Vec<String>,
     #[darling(default)]
     scale: Option<String>,
+    #[darling(default)]
+    deprecated: Option<Override<String>>,
+    #[darling(default = "default_true")]
+    served: bool,
+    #[darling(default = "default_true")]
+    storage: bool,
+}
+
+/// Values we can parse from #[kube(attrs)] on fields of the spec struct
+#[derive(Debug, FromField)]
+#[darling(attributes(kube), forward_attrs(serde))]
+struct KubeFieldAttrs {
+    ident: Option<Ident>,
+    attrs: Vec<Attribute>,
+    #[darling(default)]
+    list_type: Option<String>,
+    #[darling(multiple, rename = "list_map_key")]
+    list_map_keys: Vec<String>,
+    #[darling(default)]
+    map_type: Option<String>,
+    #[darling(default)]
+    embedded_resource: bool,
+    #[darling(default)]
+    preserve_unknown_fields: bool,
+    #[darling(default)]
+    int_or_string: bool,
+    #[darling(multiple, rename = "validation")]
+    validations: Vec<String>,
+}
+
+impl KubeFieldAttrs {
+    /// The `x-kubernetes-*` extensions to merge into the schema of this field
+    fn extensions(&self) -> syn::Result<serde_json::Map<String, serde_json::Value>> {
+        let mut exts = serde_json::Map::new();
+        if !self.validations.is_empty() {
+            // k8s-openapi's JSONSchemaProps has no `x_kubernetes_validations` and would drop the rules
+            return Err(syn::Error::new_spanned(
+                &self.ident,
+                r#"#[kube(validation = "...")] is not supported: x-kubernetes-validations can not be represented in the generated crd"#,
+            ));
+        }
+        if let Some(list_type) = &self.list_type {
+            if !matches!(list_type.as_str(), "atomic" | "set" | "map") {
+                return Err(syn::Error::new_spanned(
+                    &self.ident,
+                    r#"#[kube(list_type = "...")] must be one of "atomic", "set" or "map""#,
+                ));
+            }
+            exts.insert("x-kubernetes-list-type".into(), list_type.as_str().into());
+        }
+        if !self.list_map_keys.is_empty() {
+            if self.list_type.as_deref() != Some("map") {
+                return Err(syn::Error::new_spanned(
+                    &self.ident,
+                    r#"#[kube(list_map_key = "...")] requires #[kube(list_type = "map")]"#,
+                ));
+            }
+            exts.insert(
+                "x-kubernetes-list-map-keys".into(),
+                self.list_map_keys.clone().into(),
+            );
+        }
+        if let Some(map_type) = &self.map_type {
+            if !matches!(map_type.as_str(), "granular" | "atomic") {
+                return Err(syn::Error::new_spanned(
+                    &self.ident,
+                    r#"#[kube(map_type = "...")] must be one of "granular" or "atomic""#,
+                ));
+            }
+            exts.insert("x-kubernetes-map-type".into(), map_type.as_str().into());
+        }
+        if self.embedded_resource {
+            exts.insert("x-kubernetes-embedded-resource".into(), true.into());
+        }
+        if self.preserve_unknown_fields {
+            exts.insert("x-kubernetes-preserve-unknown-fields".into(), true.into());
+        }
+        if self.int_or_string {
+            exts.insert("x-kubernetes-int-or-string".into(), true.into());
+        }
+        Ok(exts)
+    }
+
+    /// Rejects serde attributes that move the field out of the properties of the spec schema
+    fn check_serde_attrs(&self) -> syn::Result<()> {
+        for key in &["flatten", "skip", "skip_serializing", "skip_deserializing"] {
+            if serde_attr_present(&self.attrs, key) {
+                return Err(syn::Error::new_spanned(
+                    &self.ident,
+                    format!(
+                        "field level #[kube(attrs)] can not be used on #[serde({})] fields",
+                        key
+                    ),
+                ));
+            }
+        }
+        if serde_attr_present(&self.attrs, "rename") && serde_attr_value(&self.attrs, "rename").is_none() {
+            return Err(syn::Error::new_spanned(
+                &self.ident,
+                r#"field level #[kube(attrs)] require a single #[serde(rename = "...")]"#,
+            ));
+        }
+        Ok(())
+    }
+
+    /// The property name of this field as seen by serde (and thus schemars)
+    fn property_name(&self, rename_all: Option<&str>) -> Option<String> {
+        if let Some(rename) = serde_attr_value(&self.attrs, "rename") {
+            return Some(rename);
+        }
+        let ident = self.ident.as_ref()?.to_string();
+        let ident = ident.trim_start_matches("r#");
+        Some(match rename_all {
+            Some(rule) => rename_field(ident, rule),
+            None => ident.to_owned(),
+        })
+    }
+}
+
+/// Find the string value of `key` in `#[serde(key = "value")]` attributes
+fn serde_attr_value(attrs: &[Attribute], key: &str) -> Option<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|nested| match nested {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(key) => match nv.lit {
+                Lit::Str(s) => Some(s.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
+/// Whether `key` is set in `#[serde(...)]` attributes, in any form
+fn serde_attr_present(attrs: &[Attribute], key: &str) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .any(|nested| matches!(nested, NestedMeta::Meta(meta) if meta.path().is_ident(key)))
+}
+
+/// Apply a serde `rename_all` rule to a snake_case field name
+fn rename_field(field: &str, rule: &str) -> String {
+    let pascal = || -> String {
+        field
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                    None => String::new(),
+                }
+            })
+            .collect()
+    };
+    match rule {
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        }
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.to_ascii_uppercase().replace('_', "-"),
+        // "lowercase" and "snake_case" leave snake_case fields untouched
+        _ => field.to_owned(),
+    }
 }
 
 fn default_apiext() -> String {
     "v1".to_owned()
 }
 
+fn default_true() -> bool {
+    true
+}
+
 pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
     let derive_input: DeriveInput = match syn::parse2(input) {
         Err(err) => return err.to_compile_error(),
@@ -59,6 +244,17 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         Err(err) => return err.write_errors(),
         Ok(attrs) => attrs,
     };
+    let mut field_attrs = vec![];
+    if let Data::Struct(data) = &derive_input.data {
+        if let Fields::Named(fields) = &data.fields {
+            for field in &fields.named {
+                match KubeFieldAttrs::from_field(field) {
+                    Err(err) => return err.write_errors(),
+                    Ok(attrs) => field_attrs.push(attrs),
+                }
+            }
+        }
+    }
 
     let KubeAttrs {
         group,
@@ -75,6 +271,9 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         printcolums,
         apiextensions,
         scale,
+        deprecated,
+        served,
+        storage,
     } = kube_attrs;
 
     let struct_name = kind_struct.unwrap_or_else(|| kind.clone());
@@ -262,6 +461,51 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
     let short_json = serde_json::to_string(&shortnames).unwrap();
     let crd_meta_name = format!("{}.{}", plural, group);
     let crd_meta = quote! { { "name": #crd_meta_name } };
+    let is_deprecated = if deprecated.is_some() {
+        quote! { Some(true) }
+    } else {
+        quote! { None::<bool> }
+    };
+    let deprecation_warning = match &deprecated {
+        Some(Override::Explicit(warning)) => quote! { Some(#warning) },
+        _ => quote! { None::<&str> },
+    };
+
+    // Collect field level extensions as (json pointer into the schema, extensions json)
+    let rename_all = serde_attr_value(&derive_input.attrs, "rename_all");
+    let mut field_exts = vec![];
+    for attrs in &field_attrs {
+        let exts = match attrs.extensions() {
+            Err(err) => return err.to_compile_error(),
+            Ok(exts) => exts,
+        };
+        if exts.is_empty() {
+            continue;
+        }
+        if !schema_gen_enabled {
+            return syn::Error::new_spanned(
+                &attrs.ident,
+                "field level #[kube(attrs)] require schema generation with apiextensions v1",
+            )
+            .to_compile_error();
+        }
+        if let Err(err) = attrs.check_serde_attrs() {
+            return err.to_compile_error();
+        }
+        if rename_all.is_none() && serde_attr_present(&derive_input.attrs, "rename_all") {
+            return syn::Error::new_spanned(
+                &ident,
+                r#"field level #[kube(attrs)] require a single #[serde(rename_all = "...")]"#,
+            )
+            .to_compile_error();
+        }
+        if let Some(prop) = attrs.property_name(rename_all.as_deref()) {
+            let prop = prop.replace('~', "~0").replace('/', "~1");
+            let pointer = format!("/properties/spec/properties/{}", prop);
+            let json = serde_json::to_string(&exts).unwrap();
+            field_exts.push(quote! { (#pointer, #json) });
+        }
+    }
 
     let schemagen = if schema_gen_enabled {
         quote! {
@@ -270,7 +514,17 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
                 s.inline_subschemas = true;
                 s.meta_schema = None;
             }).into_generator();
-            let schema = gen.into_root_schema_for::<Self>();
+            let mut schema = serde_json::to_value(gen.into_root_schema_for::<Self>()).expect("valid schema json");
+            // Merge field level #[kube(attrs)] into the properties of the spec
+            let field_exts: &[(&str, &str)] = &[#(#field_exts),*];
+            for (pointer, exts) in field_exts {
+                let exts: serde_json::Map<String, serde_json::Value> = serde_json::from_str(exts).expect("valid field extension json");
+                schema
+                    .pointer_mut(pointer)
+                    .and_then(|prop| prop.as_object_mut())
+                    .expect("annotated field in generated schema")
+                    .extend(exts);
+            }
         }
     } else {
         // we could issue a compile time warning for this, but it would hit EVERY compile, which would be noisy
@@ -299,8 +553,10 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
                     },
                     "versions": [{
                         "name": #version,
-                        "served": true,
-                        "storage": true,
+                        "served": #served,
+                        "storage": #storage,
+                        "deprecated": #is_deprecated,
+                        "deprecationWarning": #deprecation_warning,
                         "schema": {
                             "openAPIV3Schema": schema,
                         },
@@ -329,8 +585,10 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
                     "additionalPrinterColumns": columns,
                     "versions": [{
                         "name": #version,
-                        "served": true,
-                        "storage": true,
+                        "served": #served,
+                        "storage": #storage,
+                        "deprecated": #is_deprecated,
+                        "deprecationWarning": #deprecation_warning,
                     }],
                     "subresources": subres,
                 }
@@ -433,5 +691,46 @@ mod tests {
         let input = syn::parse2(input).unwrap();
         let kube_attrs = KubeAttrs::from_derive_input(&input).unwrap();
         assert_eq!(kube_attrs.apiextensions, "v1");
+        assert!(kube_attrs.served);
+        assert!(kube_attrs.storage);
+        assert!(kube_attrs.deprecated.is_none());
+    }
+
+    #[test]
+    fn test_deprecated_and_version_flags() {
+        let input = quote! {
+            #[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+            #[kube(group = "clux.dev", version = "v1", kind = "Foo", deprecated, storage = false)]
+            struct FooSpec { foo: String }
+        };
+        let input = syn::parse2(input).unwrap();
+        let kube_attrs = KubeAttrs::from_derive_input(&input).unwrap();
+        assert_eq!(kube_attrs.deprecated, Some(Override::Inherit));
+        assert!(kube_attrs.served);
+        assert!(!kube_attrs.storage);
+
+        let input = quote! {
+            #[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+            #[kube(group = "clux.dev", version = "v1", kind = "Foo", deprecated = "use v2")]
+            struct FooSpec { foo: String }
+        };
+        let input = syn::parse2(input).unwrap();
+        let kube_attrs = KubeAttrs::from_derive_input(&input).unwrap();
+        assert_eq!(
+            kube_attrs.deprecated,
+            Some(Override::Explicit("use v2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rename_field() {
+        assert_eq!(rename_field("list_map_key", "camelCase"), "listMapKey");
+        assert_eq!(rename_field("list_map_key", "PascalCase"), "ListMapKey");
+        assert_eq!(rename_field("list_map_key", "kebab-case"), "list-map-key");
+        assert_eq!(
+            rename_field("list_map_key", "SCREAMING_SNAKE_CASE"),
+            "LIST_MAP_KEY"
+        );
+        assert_eq!(rename_field("list_map_key", "snake_case"), "list_map_key");
     }
 }
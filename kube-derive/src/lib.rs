@@ -104,6 +104,72 @@ mod custom_resource;
 /// ### `#[kube(shortname = "sn")]`
 /// Add a single shortname to the generated crd.
 ///
+/// ### `#[kube(category = "all")]`
+/// Add a single category to the generated crd.
+///
+/// ### `#[kube(deprecated)]` or `#[kube(deprecated = "warning")]`
+/// Marks the version as [deprecated](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definition-versioning/#version-deprecation).
+/// The optional string overrides the default `deprecationWarning` returned by the apiserver.
+///
+/// ### `#[kube(served = false)]`
+/// Stop serving this version via REST APIs. Defaults to `true`.
+///
+/// ### `#[kube(storage = false)]`
+/// Do not use this version when persisting custom resources to storage. Defaults to `true`.
+/// Exactly one version of a multi-version crd must be the storage version.
+///
+/// ### Unsupported version properties
+/// `selectableFields` can not be set, because the `CustomResourceDefinitionVersion` of the supported
+/// `k8s-openapi` versions has no such field. Add it to the serialized crd if you need it.
+/// Categories are shared by all versions of a crd, so `#[kube(category)]` can not differ per version.
+///
+/// ## Optional field level `#[kube]` attributes
+///
+/// Fields of the spec struct can set [kubernetes extensions](https://kubernetes.io/docs/reference/using-api/server-side-apply/#merge-strategy)
+/// on their generated property schema. These require schema generation (i.e. `apiextensions = "v1"`).
+///
+/// ### `#[kube(list_type = "map")]`
+/// Sets `x-kubernetes-list-type` to one of `atomic`, `set` or `map`.
+///
+/// ### `#[kube(list_map_key = "name")]`
+/// Add a single key to `x-kubernetes-list-map-keys`. Requires `list_type = "map"`.
+///
+/// ### `#[kube(map_type = "atomic")]`
+/// Sets `x-kubernetes-map-type` to one of `granular` or `atomic`.
+///
+/// ### `#[kube(embedded_resource)]`
+/// Sets `x-kubernetes-embedded-resource` for fields containing a full kubernetes object.
+///
+/// ### `#[kube(preserve_unknown_fields)]`
+/// Sets `x-kubernetes-preserve-unknown-fields` to stop the apiserver from pruning the field.
+///
+/// ### `#[kube(int_or_string)]`
+/// Sets `x-kubernetes-int-or-string` for fields accepting either an integer or a string.
+///
+/// ### `#[kube(validation = "rule")]`
+/// **Not supported.** [CEL validation rules](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#validation-rules)
+/// (`x-kubernetes-validations`) can not be represented by the `JSONSchemaProps` of the supported `k8s-openapi`
+/// versions, so they would be dropped from the generated crd. Using the attribute is a compile error.
+///
+/// ```rust
+/// use serde::{Serialize, Deserialize};
+/// use kube_derive::CustomResource;
+/// use schemars::JsonSchema;
+///
+/// #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+/// #[kube(group = "clux.dev", version = "v1", kind = "Baz")]
+/// struct BazSpec {
+///     #[kube(list_type = "map", list_map_key = "name")]
+///     ports: Vec<Port>,
+/// }
+///
+/// #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+/// struct Port {
+///     name: String,
+///     port: i32,
+/// }
+/// ```
+///
 /// ## Example with all properties
 ///
 /// ```rust
@@ -123,6 +189,9 @@ mod custom_resource;
 ///     singular = "foot",
 ///     plural = "feetz",
 ///     shortname = "f",
+///     deprecated = "clux.dev/v1 Foo is deprecated",
+///     served = true,
+///     storage = true,
 ///     scale = r#"{"specReplicasPath":".spec.replicas", "statusReplicasPath":".status.replicas"}"#,
 ///     printcolumn = r#"{"name":"Spec", "type":"string", "description":"name of foo", "jsonPath":".spec.name"}"#
 /// )]
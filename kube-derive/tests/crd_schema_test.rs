@@ -35,6 +35,63 @@ struct FooSpec {
     timestamp: DateTime<Utc>,
 }
 
+// Field level attributes inject kubernetes extensions into the schema of the annotated property
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Bar", namespaced)]
+#[serde(rename_all = "camelCase")]
+struct BarSpec {
+    #[kube(list_type = "map", list_map_key = "name")]
+    named_items: Vec<Item>,
+    #[kube(list_type = "set")]
+    #[serde(rename = "tagSet")]
+    tags: Vec<String>,
+    #[kube(map_type = "atomic")]
+    labels: std::collections::BTreeMap<String, String>,
+    #[kube(embedded_resource, preserve_unknown_fields)]
+    template: serde_json::Value,
+}
+
+// Older versions can be kept around as served but deprecated
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(
+    group = "clux.dev",
+    version = "v1beta1",
+    kind = "Baz",
+    storage = false,
+    deprecated = "clux.dev/v1beta1 Baz is deprecated; use clux.dev/v1 Baz"
+)]
+struct BazSpec {
+    field: String,
+}
+
+// The next revision of `Bar`, to check what `compat::compare` reports between derived schemas
+mod next {
+    use super::Item;
+    use kube_derive::CustomResource;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+    #[kube(group = "clux.dev", version = "v1", kind = "Bar", namespaced)]
+    #[serde(rename_all = "camelCase")]
+    pub struct BarSpec {
+        #[kube(list_type = "map", list_map_key = "name")]
+        named_items: Vec<Item>,
+        #[kube(list_type = "atomic")]
+        #[serde(rename = "tagSet")]
+        tags: Vec<String>,
+        labels: std::collections::BTreeMap<String, String>,
+        #[kube(embedded_resource)]
+        template: serde_json::Value,
+        replicas: Option<i32>,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Item {
+    name: String,
+}
+
 fn default_value() -> String {
     "default_value".into()
 }
@@ -136,3 +193,73 @@ fn test_crd_schema_matches_expected() {
         .unwrap()
     );
 }
+
+#[test]
+fn test_crd_field_extensions() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Bar::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["namedItems"]["x-kubernetes-list-type"], "map");
+    assert_eq!(
+        props["namedItems"]["x-kubernetes-list-map-keys"],
+        serde_json::json!(["name"])
+    );
+    assert_eq!(props["tagSet"]["x-kubernetes-list-type"], "set");
+    assert_eq!(props["labels"]["x-kubernetes-map-type"], "atomic");
+    assert_eq!(props["template"]["x-kubernetes-embedded-resource"], true);
+    assert_eq!(props["template"]["x-kubernetes-preserve-unknown-fields"], true);
+}
+
+#[test]
+fn test_crd_version_flags() {
+    use kube::core::CustomResourceExt;
+    let version = &Baz::crd().spec.versions[0];
+    assert_eq!(version.name, "v1beta1");
+    assert!(version.served);
+    assert!(!version.storage);
+    assert_eq!(version.deprecated, Some(true));
+    assert_eq!(
+        version.deprecation_warning.as_deref(),
+        Some("clux.dev/v1beta1 Baz is deprecated; use clux.dev/v1 Baz")
+    );
+    let version = &Foo::crd().spec.versions[0];
+    assert_eq!(version.deprecated, None);
+    assert_eq!(version.deprecation_warning, None);
+}
+
+#[test]
+fn test_crd_compat_between_derived_versions() {
+    use kube::core::{
+        crd::compat::{compare, Change},
+        CustomResourceExt,
+    };
+    assert!(compare(&Bar::crd(), &Bar::crd()).changes.is_empty());
+
+    let report = compare(&Bar::crd(), &next::Bar::crd());
+    let changes: Vec<_> = report
+        .changes
+        .iter()
+        .map(|c| (c.path.as_str(), &c.change, c.is_breaking()))
+        .collect();
+    assert_eq!(changes, vec![
+        (
+            ".spec.labels",
+            &Change::MapSemanticsChanged {
+                old: "atomic".into(),
+                new: "granular".into()
+            },
+            true
+        ),
+        (
+            ".spec.tagSet",
+            &Change::ListSemanticsChanged {
+                old: "set".into(),
+                new: "atomic".into()
+            },
+            true
+        ),
+        (".spec.template", &Change::PreserveUnknownFieldsRemoved, true),
+        (".spec.replicas", &Change::FieldAdded, false),
+    ]);
+    assert!(!report.is_compatible());
+}
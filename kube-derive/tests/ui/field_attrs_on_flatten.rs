@@ -0,0 +1,14 @@
+use kube_derive::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+struct FooSpec {
+    #[kube(map_type = "atomic")]
+    #[serde(flatten)]
+    labels: BTreeMap<String, String>,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use kube_derive::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+struct FooSpec {
+    #[kube(list_type = "set")]
+    #[serde(rename(serialize = "ports", deserialize = "port"))]
+    ports: Vec<u16>,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use kube_derive::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+struct FooSpec {
+    #[kube(validation = "self.minReplicas <= self.replicas")]
+    replicas: i32,
+}
+
+fn main() {}
@@ -130,6 +130,26 @@ where
     })
 }
 
+// Sets the cluster of the requests of a `Controller`
+fn on_cluster<K, S>(
+    stream: S,
+    cluster: Option<String>,
+) -> impl Stream<Item = Result<ReconcileRequest<K>, S::Error>>
+where
+    S: TryStream<Ok = ReconcileRequest<K>>,
+    K: Resource,
+{
+    stream.map_ok(move |mut request| {
+        if let Some(cluster) = &cluster {
+            request.obj_ref.cluster = Some(cluster.clone());
+            if let ReconcileReason::RelatedObjectUpdated { obj_ref } = &mut request.reason {
+                obj_ref.cluster = Some(cluster.clone());
+            }
+        }
+        request
+    })
+}
+
 /// A context data type that's passed through to the controllers callbacks
 ///
 /// `Context` gets passed to both the `reconciler` and the `error_policy` callbacks,
@@ -270,6 +290,7 @@ where
                 let request = request.clone();
                 match store.get(&request.obj_ref) {
                     Some(obj) => {
+                        // Requests sent by the reconciler are traced as children of this span
                         let reconciler_span = info_span!("reconciling object", "object.ref" = %request.obj_ref, object.reason = %request.reason);
                         reconciler_span.in_scope(|| reconciler(obj, context.clone()))
                         .into_future()
@@ -407,6 +428,7 @@ where
     forceful_shutdown_selector: Vec<BoxFuture<'static, ()>>,
     dyntype: K::DynamicType,
     reader: Store<K>,
+    cluster: Option<String>,
 }
 
 impl<K> Controller<K>
@@ -468,6 +490,7 @@ where
             ],
             dyntype,
             reader,
+            cluster: None,
         }
     }
 
@@ -634,6 +657,50 @@ where
         self
     }
 
+    /// Set the cluster that the [`Api`] of the `Controller` talks to
+    ///
+    /// The cluster is set on the [`ObjectRef`]s of every reconciliation, so the output and errors
+    /// of controllers for different clusters can be told apart. Use one `Controller` per cluster,
+    /// and pass anything the reconciler needs to know about its cluster through the [`Context`].
+    ///
+    /// ```rust
+    /// # async {
+    /// use futures::{stream, StreamExt};
+    /// use k8s_openapi::api::core::v1::ConfigMap;
+    /// use kube::{api::ListParams, client::ClusterRegistry, Api, Client, ResourceExt};
+    /// use kube_runtime::controller::{Context, Controller, ReconcilerAction};
+    /// use std::convert::Infallible;
+    /// let registry = ClusterRegistry::new();
+    /// let mut controllers = Vec::new();
+    /// for cluster in registry.clusters() {
+    ///     let client = registry.client(&cluster).await.unwrap();
+    ///     let controller = Controller::new(Api::<ConfigMap>::all(client.clone()), ListParams::default())
+    ///         .cluster(&cluster)
+    ///         .run(
+    ///             |o, _| async move {
+    ///                 println!("Reconciling {}", o.name());
+    ///                 Ok(ReconcilerAction { requeue_after: None })
+    ///             },
+    ///             |_: &Infallible, _| ReconcilerAction { requeue_after: None },
+    ///             Context::new(client),
+    ///         );
+    ///     controllers.push(controller.boxed());
+    /// }
+    /// stream::select_all(controllers).for_each(|res| async move {
+    ///     if let Ok((obj_ref, _)) = res {
+    ///         println!("Reconciled {} in cluster {:?}", obj_ref, obj_ref.cluster);
+    ///     }
+    /// }).await;
+    /// # };
+    /// ```
+    ///
+    /// [`Api`]: kube::Api
+    #[must_use]
+    pub fn cluster(mut self, cluster: &str) -> Self {
+        self.cluster = Some(cluster.to_string());
+        self
+    }
+
     /// Start a graceful shutdown when `trigger` resolves. Once a graceful shutdown has been initiated:
     ///
     /// - No new reconciliations are started from the scheduler
@@ -754,7 +821,7 @@ where
             error_policy,
             context,
             self.reader,
-            self.trigger_selector
+            on_cluster(self.trigger_selector, self.cluster)
                 .take_until(future::select_all(self.graceful_shutdown_selector)),
         )
         .take_until(futures::future::select_all(self.forceful_shutdown_selector))
@@ -764,9 +831,24 @@ where
 #[cfg(test)]
 mod tests {
     use super::{Context, ReconcilerAction};
-    use crate::Controller;
+    use crate::{
+        finalizer::{finalizer, Event},
+        Controller,
+    };
+    use futures::{channel::oneshot, FutureExt, StreamExt};
     use k8s_openapi::api::core::v1::ConfigMap;
-    use kube::Api;
+    use kube::{
+        api::{DeleteParams, Patch, PatchParams, PostParams},
+        fake::FakeApiServer,
+        Api,
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
 
     fn assert_send<T: Send>(x: T) -> T {
         x
@@ -789,4 +871,99 @@ mod tests {
             ),
         );
     }
+
+    // Polls `check` until it holds
+    async fn eventually<F: futures::Future<Output = bool>>(check: impl Fn() -> F) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !check().await {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("condition was not met in time");
+    }
+
+    #[tokio::test]
+    async fn reconciles_and_finalizes_against_fake_apiserver() {
+        let server = FakeApiServer::new();
+        let cms: Api<ConfigMap> = Api::default_namespaced(server.client());
+        let cleaned_up = Arc::new(AtomicBool::new(false));
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        let controller = Controller::new(cms.clone(), Default::default())
+            .graceful_shutdown_on(shutdown_rx.map(|_| ()))
+            .run(
+                |cm, ctx: Context<(Api<ConfigMap>, Arc<AtomicBool>)>| async move {
+                    let (cms, cleaned_up) = ctx.get_ref();
+                    finalizer(cms, "kube-rs.test/cleanup", cm, |event| async move {
+                        match event {
+                            Event::Apply(cm) => {
+                                let patch =
+                                    Patch::Merge(serde_json::json!({ "data": { "reconciled": "true" } }));
+                                let name = cm.metadata.name.unwrap();
+                                cms.patch(&name, &PatchParams::default(), &patch).await?;
+                            }
+                            Event::Cleanup(_) => cleaned_up.store(true, Ordering::SeqCst),
+                        }
+                        Ok::<_, kube::Error>(ReconcilerAction { requeue_after: None })
+                    })
+                    .await
+                },
+                |_, _| ReconcilerAction {
+                    requeue_after: Some(Duration::from_millis(10)),
+                },
+                Context::new((cms.clone(), cleaned_up.clone())),
+            )
+            .for_each(|_| async {});
+        let controller = tokio::spawn(controller);
+
+        let cm = serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "test" },
+        }))
+        .unwrap();
+        cms.create(&PostParams::default(), &cm).await.unwrap();
+        eventually(|| async {
+            let cm = cms.get("test").await.unwrap();
+            cm.metadata.finalizers == vec!["kube-rs.test/cleanup".to_string()]
+                && cm.data.contains_key("reconciled")
+        })
+        .await;
+
+        cms.delete("test", &DeleteParams::default()).await.unwrap();
+        eventually(|| async { cms.get("test").await.is_err() }).await;
+        assert!(cleaned_up.load(Ordering::SeqCst));
+
+        shutdown_tx.send(()).unwrap();
+        controller.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sets_cluster_on_reconciled_refs() {
+        let server = FakeApiServer::new();
+        let cms: Api<ConfigMap> = Api::default_namespaced(server.client());
+        let cm = serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "test" },
+        }))
+        .unwrap();
+        cms.create(&PostParams::default(), &cm).await.unwrap();
+
+        let (obj_ref, _) = Controller::new(cms, Default::default())
+            .cluster("a")
+            .run(
+                |_, _| async { Ok::<_, std::convert::Infallible>(ReconcilerAction { requeue_after: None }) },
+                |_, _| ReconcilerAction { requeue_after: None },
+                Context::new(()),
+            )
+            .boxed()
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(obj_ref.name, "test");
+        assert_eq!(obj_ref.cluster.as_deref(), Some("a"));
+    }
 }
@@ -0,0 +1,256 @@
+//! Helpers for installing `CustomResourceDefinition`s and waiting for them to be usable
+
+use crate::watcher;
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::{
+    api::{ListParams, Patch, PatchParams},
+    discovery::Discovery,
+    Api, Client, CustomResourceExt, Resource,
+};
+use snafu::{futures::TryStreamExt as _, ResultExt, Snafu};
+use std::time::Duration;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("crd has no name"))]
+    UnnamedCrd,
+    #[snafu(display("failed to apply crd: {}", source))]
+    ApplyFailed { source: kube::Error },
+    #[snafu(display("failed to watch crd: {}", source))]
+    WatchFailed { source: watcher::Error },
+    #[snafu(display("crd names were not accepted ({}): {}", reason, message))]
+    NamesNotAccepted { reason: String, message: String },
+    #[snafu(display("crd was deleted while waiting for it to be established"))]
+    Deleted,
+    #[snafu(display("crd was not established within {:?}", timeout))]
+    Timeout { timeout: Duration },
+    #[snafu(display("failed to refresh discovery: {}", source))]
+    DiscoveryFailed { source: kube::Error },
+}
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Checks the status conditions of a `CustomResourceDefinition`
+///
+/// Returns `Ok(true)` when the crd is `Established` and its names are accepted,
+/// and an [`Error::NamesNotAccepted`] if the apiserver rejected its names (e.g. because of a conflict).
+///
+/// # Errors
+///
+/// Returns [`Error::NamesNotAccepted`] if the `NamesAccepted` condition is `False`.
+pub fn is_established(crd: &CustomResourceDefinition) -> Result<bool> {
+    let conditions = crd
+        .status
+        .as_ref()
+        .map(|s| s.conditions.as_slice())
+        .unwrap_or_default();
+    let condition = |type_: &str| conditions.iter().find(|c| c.type_ == type_);
+    if let Some(cond) = condition("NamesAccepted") {
+        if cond.status == "False" {
+            return NamesNotAccepted {
+                reason: cond.reason.clone().unwrap_or_default(),
+                message: cond.message.clone().unwrap_or_default(),
+            }
+            .fail();
+        }
+    }
+    let is_true = |type_: &str| matches!(condition(type_), Some(c) if c.status == "True");
+    Ok(is_true("NamesAccepted") && is_true("Established"))
+}
+
+/// Checks a watch event of the crd being waited for, returning it once it is established
+///
+/// The watch only selects the crd by name, so a `Restarted` event without it means that it was deleted.
+fn established_by(
+    event: watcher::Event<CustomResourceDefinition>,
+) -> Result<Option<CustomResourceDefinition>> {
+    let crd = match event {
+        watcher::Event::Applied(crd) => crd,
+        watcher::Event::Deleted(_) => return Deleted.fail(),
+        watcher::Event::Restarted(crds) => match crds.into_iter().next() {
+            Some(crd) => crd,
+            None => return Deleted.fail(),
+        },
+    };
+    if crd.meta().deletion_timestamp.is_some() {
+        return Deleted.fail();
+    }
+    Ok(if is_established(&crd)? { Some(crd) } else { None })
+}
+
+/// Server-side applies a `CustomResourceDefinition` and waits until it is established
+///
+/// The crd is applied with `field_manager` (and forced, taking ownership of conflicting fields),
+/// after which the crd is watched until both the `Established` and `NamesAccepted` conditions are true,
+/// at which point custom resources of this type can be used.
+///
+/// If a [`Discovery`] is passed, the crd's api group is refreshed in it once the crd is established.
+///
+/// ```no_run
+/// use kube::{Client, CustomResourceExt};
+/// use kube_derive::CustomResource;
+/// use kube_runtime::crd::install_crd;
+/// use schemars::JsonSchema;
+/// use serde::{Deserialize, Serialize};
+/// use std::time::Duration;
+///
+/// #[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+/// #[kube(group = "clux.dev", version = "v1", kind = "Foo", namespaced)]
+/// struct FooSpec {
+///     info: String,
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let client = Client::try_default().await?;
+///     install_crd(client, &Foo::crd(), "foo-controller", Duration::from_secs(10), None).await?;
+///     Ok(())
+/// }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the crd could not be applied or watched, if its names were not accepted,
+/// if it was deleted while waiting, or if it was not established within `timeout`.
+pub async fn install_crd(
+    client: Client,
+    crd: &CustomResourceDefinition,
+    field_manager: &str,
+    timeout: Duration,
+    discovery: Option<&mut Discovery>,
+) -> Result<CustomResourceDefinition> {
+    let name = crd.meta().name.clone().ok_or(Error::UnnamedCrd)?;
+    let api = Api::<CustomResourceDefinition>::all(client);
+    let pp = PatchParams::apply(field_manager).force();
+    let applied = api
+        .patch(&name, &pp, &Patch::Apply(crd))
+        .await
+        .context(ApplyFailed)?;
+
+    let established = if is_established(&applied)? {
+        applied
+    } else {
+        let lp = ListParams::default().fields(&format!("metadata.name={name}"));
+        let wait = async {
+            let mut events = watcher(api, lp).context(WatchFailed).boxed();
+            while let Some(event) = events.try_next().await? {
+                if let Some(crd) = established_by(event)? {
+                    return Ok(crd);
+                }
+            }
+            Deleted.fail()
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| Error::Timeout { timeout })??
+    };
+
+    if let Some(discovery) = discovery {
+        discovery
+            .refresh_group(&established.spec.group)
+            .await
+            .context(DiscoveryFailed)?;
+    }
+    Ok(established)
+}
+
+/// Installs the `CustomResourceDefinition` generated by `kube-derive` for `K`
+///
+/// See [`install_crd`] for details.
+///
+/// # Errors
+///
+/// See [`install_crd`].
+pub async fn install<K: CustomResourceExt>(
+    client: Client,
+    field_manager: &str,
+    timeout: Duration,
+    discovery: Option<&mut Discovery>,
+) -> Result<CustomResourceDefinition> {
+    install_crd(client, &K::crd(), field_manager, timeout, discovery).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{established_by, is_established, Error};
+    use crate::watcher;
+    use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+
+    fn crd_with_conditions(conditions: &serde_json::Value) -> CustomResourceDefinition {
+        serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [],
+            },
+            "status": { "conditions": conditions },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn established_requires_both_conditions() {
+        let crd = crd_with_conditions(&serde_json::json!([]));
+        assert!(!is_established(&crd).unwrap());
+        let crd = crd_with_conditions(&serde_json::json!([
+            { "type": "NamesAccepted", "status": "True" },
+        ]));
+        assert!(!is_established(&crd).unwrap());
+        let crd = crd_with_conditions(&serde_json::json!([
+            { "type": "NamesAccepted", "status": "True" },
+            { "type": "Established", "status": "True" },
+        ]));
+        assert!(is_established(&crd).unwrap());
+    }
+
+    #[test]
+    fn name_conflicts_are_errors() {
+        let crd = crd_with_conditions(&serde_json::json!([
+            {
+                "type": "NamesAccepted",
+                "status": "False",
+                "reason": "MultipleNameConflict",
+                "message": "\"foos\" is already in use",
+            },
+            { "type": "Established", "status": "False" },
+        ]));
+        match is_established(&crd) {
+            Err(Error::NamesNotAccepted { reason, .. }) => assert_eq!(reason, "MultipleNameConflict"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deletions_end_the_wait() {
+        let established = crd_with_conditions(&serde_json::json!([
+            { "type": "NamesAccepted", "status": "True" },
+            { "type": "Established", "status": "True" },
+        ]));
+        let pending = crd_with_conditions(&serde_json::json!([]));
+        assert!(established_by(watcher::Event::Applied(pending.clone()))
+            .unwrap()
+            .is_none());
+        assert!(
+            established_by(watcher::Event::Restarted(vec![established.clone()]))
+                .unwrap()
+                .is_some()
+        );
+        assert!(matches!(
+            established_by(watcher::Event::Deleted(pending)),
+            Err(Error::Deleted)
+        ));
+        assert!(matches!(
+            established_by(watcher::Event::Restarted(vec![])),
+            Err(Error::Deleted)
+        ));
+        let mut deleting = serde_json::to_value(&established).unwrap();
+        deleting["metadata"]["deletionTimestamp"] = "2021-01-01T00:00:00Z".into();
+        let deleting = serde_json::from_value(deleting).unwrap();
+        assert!(matches!(
+            established_by(watcher::Event::Applied(deleting)),
+            Err(Error::Deleted)
+        ));
+    }
+}
@@ -18,6 +18,7 @@
 #![allow(clippy::type_repetition_in_bounds)]
 
 pub mod controller;
+pub mod crd;
 pub mod finalizer;
 pub mod reflector;
 pub mod scheduler;
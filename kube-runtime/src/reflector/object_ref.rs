@@ -44,6 +44,16 @@ pub struct ObjectRef<K: Resource> {
     /// assert_ne!(ObjectRef::<ConfigMap>::new("foo"), ObjectRef::new("foo").within("bar"));
     /// ```
     pub namespace: Option<String>,
+    /// The cluster of the object, when objects of several clusters are handled together
+    ///
+    /// `ObjectRef`s to objects with the same name in different clusters are not considered equal:
+    ///
+    /// ```
+    /// # use kube_runtime::reflector::ObjectRef;
+    /// # use k8s_openapi::api::core::v1::ConfigMap;
+    /// assert_ne!(ObjectRef::<ConfigMap>::new("foo").on_cluster("a"), ObjectRef::new("foo").on_cluster("b"));
+    /// ```
+    pub cluster: Option<String>,
 }
 
 impl<K: Resource> ObjectRef<K>
@@ -71,6 +81,7 @@ impl<K: Resource> ObjectRef<K> {
             dyntype,
             name: name.into(),
             namespace: None,
+            cluster: None,
         }
     }
 
@@ -80,6 +91,13 @@ impl<K: Resource> ObjectRef<K> {
         self
     }
 
+    /// Set the cluster of the object, see [`ObjectRef::cluster`]
+    #[must_use]
+    pub fn on_cluster(mut self, cluster: &str) -> Self {
+        self.cluster = Some(cluster.to_string());
+        self
+    }
+
     /// Creates `ObjectRef` from the resource and dynamic type.
     /// Panics if name is missing (name always exists if the object
     /// was returned from the apiserver)
@@ -92,6 +110,7 @@ impl<K: Resource> ObjectRef<K> {
             dyntype,
             name: obj.name(),
             namespace: obj.namespace(),
+            cluster: None,
         }
     }
 
@@ -109,6 +128,7 @@ impl<K: Resource> ObjectRef<K> {
                 dyntype,
                 name: owner.name.clone(),
                 namespace: namespace.map(String::from),
+                cluster: None,
             })
         } else {
             None
@@ -125,6 +145,7 @@ impl<K: Resource> ObjectRef<K> {
             dyntype: dt2,
             name: self.name,
             namespace: self.namespace,
+            cluster: self.cluster,
         }
     }
 
@@ -133,6 +154,7 @@ impl<K: Resource> ObjectRef<K> {
             dyntype: kube::api::ApiResource::erase::<K>(&self.dyntype),
             name: self.name,
             namespace: self.namespace,
+            cluster: self.cluster,
         }
     }
 }
@@ -150,6 +172,9 @@ impl<K: Resource> Display for ObjectRef<K> {
         if let Some(namespace) = &self.namespace {
             write!(f, ".{}", namespace)?;
         }
+        if let Some(cluster) = &self.cluster {
+            write!(f, "@{cluster}")?;
+        }
         Ok(())
     }
 }
@@ -179,6 +204,15 @@ mod tests {
             format!("{}", ObjectRef::<Node>::new("my-node")),
             "Node.v1./my-node"
         );
+        assert_eq!(
+            format!(
+                "{}",
+                ObjectRef::<Pod>::new("my-pod")
+                    .within("my-namespace")
+                    .on_cluster("my-cluster")
+            ),
+            "Pod.v1./my-pod.my-namespace@my-cluster"
+        );
     }
 
     #[test]
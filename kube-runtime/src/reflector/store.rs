@@ -93,6 +93,7 @@ where
     /// Retrieve a `clone()` of the entry referred to by `key`, if it is in the cache.
     ///
     /// `key.namespace` is ignored for cluster-scoped resources.
+    /// `key.cluster` is ignored, since a store only caches objects of a single cluster.
     ///
     /// Note that this is a cache and may be stale. Deleted objects may still exist in the cache
     /// despite having been deleted in the cluster, and new objects may not yet exist in the cache.
@@ -101,8 +102,10 @@ where
     /// reasonable `error_policy`.
     #[must_use]
     pub fn get(&self, key: &ObjectRef<K>) -> Option<K> {
+        let mut key = key.clone();
+        key.cluster = None;
         self.store
-            .get(key)
+            .get(&key)
             // Try to erase the namespace and try again, in case the object is cluster-scoped
             .or_else(|| {
                 self.store.get(&{
@@ -196,4 +199,20 @@ mod tests {
         let store = store_w.as_reader();
         assert_eq!(store.get(&ObjectRef::from_obj(&nsed_cm)), Some(cm));
     }
+
+    #[test]
+    fn should_allow_getting_object_by_ref_with_cluster() {
+        let cm = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some("obj".to_string()),
+                namespace: Some("ns".to_string()),
+                ..ObjectMeta::default()
+            },
+            ..ConfigMap::default()
+        };
+        let mut store_w = Writer::default();
+        store_w.apply_watcher_event(&watcher::Event::Applied(cm.clone()));
+        let store = store_w.as_reader();
+        assert_eq!(store.get(&ObjectRef::from_obj(&cm).on_cluster("a")), Some(cm));
+    }
 }
@@ -1,4 +1,9 @@
-use std::{convert::TryFrom, process::Command, sync::Arc};
+use std::{
+    convert::TryFrom,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
 
 use chrono::{DateTime, Duration, Utc};
 use http::HeaderValue;
@@ -7,12 +12,13 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
 use crate::{
-    config::{read_file_to_string, AuthInfo, AuthProviderConfig, ExecConfig},
+    config::{read_file_to_string, AuthInfo, AuthProviderConfig, ExecAuthCluster, ExecConfig},
     error::{ConfigError, Error},
     Result,
 };
 
 #[cfg(feature = "oauth")] mod oauth;
+mod oidc;
 
 #[derive(Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
@@ -29,9 +35,13 @@ pub(crate) enum Auth {
 // - exec
 // - gcp: command based token source (exec)
 // - gcp: application credential based token source (requires `oauth` feature)
+// - oidc: id-token refreshed with the refresh-token from the issuer
+// - token files: re-read periodically to pick up rotated tokens, e.g. bound service account tokens
 #[derive(Debug, Clone)]
 pub(crate) enum RefreshableToken {
     Exec(Arc<Mutex<(String, DateTime<Utc>, AuthInfo)>>),
+    File(Arc<Mutex<TokenFile>>),
+    Oidc(Arc<Mutex<oidc::Oidc>>),
     #[cfg(feature = "oauth")]
     GcpOauth(Arc<Mutex<oauth::Gcp>>),
 }
@@ -59,6 +69,8 @@ impl RefreshableToken {
                         }
 
                         // Unreachable because the token source does not change
+                        Auth::RefreshableToken(RefreshableToken::File(_))
+                        | Auth::RefreshableToken(RefreshableToken::Oidc(_)) => unreachable!(),
                         #[cfg(feature = "oauth")]
                         Auth::RefreshableToken(RefreshableToken::GcpOauth(_)) => unreachable!(),
                     }
@@ -70,6 +82,22 @@ impl RefreshableToken {
                 Ok(value)
             }
 
+            RefreshableToken::File(file) => {
+                let mut locked_file = file.lock().await;
+                let mut value = HeaderValue::try_from(format!("Bearer {}", locked_file.token()))
+                    .map_err(ConfigError::InvalidBearerToken)?;
+                value.set_sensitive(true);
+                Ok(value)
+            }
+
+            RefreshableToken::Oidc(oidc) => {
+                let token = oidc.lock().await.token().await?;
+                let mut value = HeaderValue::try_from(format!("Bearer {}", token))
+                    .map_err(ConfigError::InvalidBearerToken)?;
+                value.set_sensitive(true);
+                Ok(value)
+            }
+
             #[cfg(feature = "oauth")]
             RefreshableToken::GcpOauth(data) => {
                 let gcp_oauth = data.lock().await;
@@ -83,6 +111,75 @@ impl RefreshableToken {
     }
 }
 
+impl RefreshableToken {
+    /// Forces the token to be refreshed before the next request, e.g. after the apiserver rejected it
+    pub(crate) fn invalidate(&self) {
+        // A refresh is already underway if the lock is held
+        match self {
+            RefreshableToken::File(file) => {
+                if let Ok(mut locked_file) = file.try_lock() {
+                    locked_file.expires_at = Utc::now();
+                }
+            }
+            RefreshableToken::Oidc(oidc) => {
+                if let Ok(mut locked_oidc) = oidc.try_lock() {
+                    locked_oidc.expire();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// How long a token read from a file is used before reading the file again.
+// This matches client-go, and is well within the lifetime of bound service account tokens.
+const TOKEN_FILE_REFRESH_PERIOD: i64 = 60;
+
+/// A token read from a file that is re-read periodically to pick up rotated tokens
+#[derive(Debug)]
+pub(crate) struct TokenFile {
+    path: PathBuf,
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl TokenFile {
+    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let token = read_file_to_string(&path)?.trim().to_string();
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            token,
+            expires_at: Utc::now() + Duration::seconds(TOKEN_FILE_REFRESH_PERIOD),
+        })
+    }
+
+    fn is_expiring(&self) -> bool {
+        Utc::now() >= self.expires_at
+    }
+
+    /// Returns the token, reading the file again if it is due
+    ///
+    /// If the file cannot be read, the previous token is used.
+    fn token(&mut self) -> &str {
+        if self.is_expiring() {
+            match read_file_to_string(&self.path) {
+                Ok(token) => {
+                    self.token = token.trim().to_string();
+                    self.expires_at = Utc::now() + Duration::seconds(TOKEN_FILE_REFRESH_PERIOD);
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "failed to reload token from {:?}, using the previous token: {}",
+                        self.path,
+                        err
+                    );
+                }
+            }
+        }
+        &self.token
+    }
+}
+
 impl TryFrom<&AuthInfo> for Auth {
     type Error = Error;
 
@@ -92,8 +189,10 @@ impl TryFrom<&AuthInfo> for Auth {
     fn try_from(auth_info: &AuthInfo) -> Result<Self, Self::Error> {
         if let Some(provider) = &auth_info.auth_provider {
             match token_from_provider(provider)? {
-                ProviderToken::Oidc(token) => {
-                    return Ok(Self::Bearer(token));
+                ProviderToken::Oidc(oidc) => {
+                    return Ok(Self::RefreshableToken(RefreshableToken::Oidc(Arc::new(
+                        Mutex::new(oidc),
+                    ))));
                 }
 
                 ProviderToken::GcpCommand(token, Some(expiry)) => {
@@ -124,39 +223,111 @@ impl TryFrom<&AuthInfo> for Auth {
             return Ok(Self::Basic(u.to_owned(), p.to_owned()));
         }
 
-        let (raw_token, expiration) = match &auth_info.token {
-            Some(token) => (Some(token.clone()), None),
-            None => {
-                if let Some(exec) = &auth_info.exec {
-                    let creds = auth_exec(exec)?;
-                    let status = creds.status.ok_or(ConfigError::ExecPluginFailed)?;
-                    let expiration = status
-                        .expiration_timestamp
-                        .map(|ts| ts.parse())
-                        .transpose()
-                        .map_err(ConfigError::MalformedTokenExpirationDate)?;
-                    (status.token, expiration)
-                } else if let Some(file) = &auth_info.token_file {
-                    (Some(read_file_to_string(file)?), None)
-                } else {
-                    (None, None)
-                }
+        if let Some(token) = &auth_info.token {
+            return Ok(Self::Bearer(token.clone()));
+        }
+
+        if let Some(exec) = &auth_info.exec {
+            let creds = auth_exec(exec)?;
+            let status = creds.status.ok_or(ConfigError::ExecPluginFailed)?;
+            return Self::from_exec_status(&status, auth_info);
+        }
+
+        if let Some(file) = &auth_info.token_file {
+            return Ok(Self::RefreshableToken(RefreshableToken::File(Arc::new(
+                Mutex::new(TokenFile::new(file)?),
+            ))));
+        }
+
+        Ok(Self::None)
+    }
+}
+
+impl Auth {
+    /// Loads the authentication like [`Auth::try_from`], along with any client certificate issued by an exec plugin
+    ///
+    /// This runs the exec plugin only once for both.
+    pub(crate) fn load(auth_info: &AuthInfo) -> Result<(Self, Option<ExecIdentity>)> {
+        // Exec plugins are only used when no other credentials are configured
+        let other_credentials = auth_info.auth_provider.is_some()
+            || (auth_info.username.is_some() && auth_info.password.is_some())
+            || auth_info.token.is_some();
+        match &auth_info.exec {
+            Some(exec) if !other_credentials => {
+                let creds = auth_exec(exec)?;
+                let status = creds.status.ok_or(ConfigError::ExecPluginFailed)?;
+                let identity = ExecIdentity::from_status(exec, &status)?;
+                Ok((Self::from_exec_status(&status, auth_info)?, identity))
             }
-        };
+            _ => Ok((Self::try_from(auth_info)?, None)),
+        }
+    }
 
-        match (raw_token, expiration) {
-            (Some(token), None) => Ok(Self::Bearer(token)),
+    fn from_exec_status(status: &ExecCredentialStatus, auth_info: &AuthInfo) -> Result<Self> {
+        match (&status.token, exec_expiration(status)?) {
+            (Some(token), None) => Ok(Self::Bearer(token.clone())),
             (Some(token), Some(expire)) => Ok(Self::RefreshableToken(RefreshableToken::Exec(Arc::new(
-                Mutex::new((token, expire, auth_info.clone())),
+                Mutex::new((token.clone(), expire, auth_info.clone())),
             )))),
             _ => Ok(Self::None),
         }
     }
 }
 
+fn exec_expiration(status: &ExecCredentialStatus) -> Result<Option<DateTime<Utc>>> {
+    Ok(status
+        .expiration_timestamp
+        .as_ref()
+        .map(|ts| ts.parse())
+        .transpose()
+        .map_err(ConfigError::MalformedTokenExpirationDate)?)
+}
+
+/// Client certificate and key issued by an exec plugin
+#[derive(Debug, Clone)]
+pub(crate) struct ExecIdentity {
+    exec: ExecConfig,
+    /// Client certificate and private key in PEM
+    pub(crate) pem: Vec<u8>,
+    expiry: Option<DateTime<Utc>>,
+}
+
+impl ExecIdentity {
+    pub(crate) fn from_status(exec: &ExecConfig, status: &ExecCredentialStatus) -> Result<Option<Self>> {
+        let (cert, key) = match (&status.client_certificate_data, &status.client_key_data) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => return Ok(None),
+        };
+        let mut pem = key.clone().into_bytes();
+        if !pem.ends_with(b"\n") {
+            pem.push(b'\n');
+        }
+        pem.extend_from_slice(cert.as_bytes());
+        Ok(Some(Self {
+            exec: exec.clone(),
+            pem,
+            expiry: exec_expiration(status)?,
+        }))
+    }
+
+    /// Whether the certificate expires within a minute and should be replaced
+    pub(crate) fn is_expiring(&self) -> bool {
+        matches!(self.expiry, Some(expiry) if Utc::now() + Duration::seconds(60) >= expiry)
+    }
+
+    /// Runs the exec plugin again for a new certificate
+    pub(crate) fn refresh(&self) -> Result<Self> {
+        let creds = auth_exec(&self.exec)?;
+        let status = creds.status.ok_or(ConfigError::ExecPluginFailed)?;
+        Self::from_status(&self.exec, &status)?.ok_or_else(|| {
+            ConfigError::AuthExec("exec plugin stopped returning a client certificate".into()).into()
+        })
+    }
+}
+
 // We need to differentiate providers because the keys/formats to store token expiration differs.
 enum ProviderToken {
-    Oidc(String),
+    Oidc(oidc::Oidc),
     // "access-token", "expiry" (RFC3339)
     GcpCommand(String, Option<DateTime<Utc>>),
     #[cfg(feature = "oauth")]
@@ -178,10 +349,7 @@ fn token_from_provider(provider: &AuthProviderConfig) -> Result<ProviderToken> {
 }
 
 fn token_from_oidc_provider(provider: &AuthProviderConfig) -> Result<ProviderToken> {
-    match provider.config.get("id-token") {
-        Some(id_token) => Ok(ProviderToken::Oidc(id_token.clone())),
-        None => Err(ConfigError::AuthExec("No id-token for oidc Authentication provider".into()).into()),
-    }
+    Ok(ProviderToken::Oidc(oidc::Oidc::from_provider(provider)?))
 }
 
 fn token_from_gcp_provider(provider: &AuthProviderConfig) -> Result<ProviderToken> {
@@ -280,14 +448,21 @@ pub struct ExecCredential {
     pub kind: Option<String>,
     #[serde(rename = "apiVersion")]
     pub api_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub spec: Option<ExecCredentialSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub status: Option<ExecCredentialStatus>,
 }
 
 /// ExecCredenitalSpec holds request and runtime specific information provided
 /// by transport.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct ExecCredentialSpec {}
+pub struct ExecCredentialSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interactive: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster: Option<ExecAuthCluster>,
+}
 
 /// ExecCredentialStatus holds credentials for the transport to use.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -315,6 +490,24 @@ fn auth_exec(auth: &ExecConfig) -> Result<ExecCredential, ConfigError> {
             });
         cmd.envs(envs);
     }
+    // Like client-go, describe the request to the plugin, including the cluster if `provideClusterInfo` is set
+    let exec_info = ExecCredential {
+        kind: Some("ExecCredential".into()),
+        api_version: Some(
+            auth.api_version
+                .clone()
+                .unwrap_or_else(|| "client.authentication.k8s.io/v1beta1".into()),
+        ),
+        spec: Some(ExecCredentialSpec {
+            interactive: Some(false),
+            cluster: auth.cluster.clone(),
+        }),
+        status: None,
+    };
+    cmd.env(
+        "KUBERNETES_EXEC_INFO",
+        serde_json::to_string(&exec_info).map_err(ConfigError::AuthExecSerialize)?,
+    );
     let out = cmd.output().map_err(ConfigError::AuthExecStart)?;
     if !out.status.success() {
         return Err(ConfigError::AuthExecRun {
@@ -333,6 +526,35 @@ mod test {
     use crate::config::Kubeconfig;
 
     use super::*;
+
+    #[test]
+    fn exec_info_provides_cluster() {
+        // The plugin echoes back the `ExecCredential` it was given
+        let exec = ExecConfig {
+            api_version: Some("client.authentication.k8s.io/v1beta1".into()),
+            command: "printenv".into(),
+            args: Some(vec!["KUBERNETES_EXEC_INFO".into()]),
+            env: None,
+            provide_cluster_info: Some(true),
+            cluster: Some(ExecAuthCluster {
+                server: "https://10.0.0.1".into(),
+                insecure_skip_tls_verify: None,
+                tls_server_name: None,
+                certificate_authority_data: Some("Y2E=".into()),
+                proxy_url: None,
+                config: Some(serde_json::json!({"audience": "kube"})),
+            }),
+            other: Default::default(),
+        };
+        let info = auth_exec(&exec).unwrap();
+        assert_eq!(info.kind.as_deref(), Some("ExecCredential"));
+        let spec = info.spec.unwrap();
+        assert_eq!(spec.interactive, Some(false));
+        let cluster = spec.cluster.unwrap();
+        assert_eq!(cluster.server, "https://10.0.0.1");
+        assert_eq!(cluster.certificate_authority_data.as_deref(), Some("Y2E="));
+        assert_eq!(cluster.config.unwrap()["audience"], "kube");
+    }
     #[tokio::test]
     async fn exec_auth_command() -> Result<()> {
         let expiry = (Utc::now() + Duration::seconds(60 * 60)).to_rfc3339();
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hyper::{
+    client::{connect::Connect, HttpConnector},
+    Body,
+};
+use serde::Deserialize;
+
+use crate::{
+    config::{certs, data_or_file_with_base64, AuthProviderConfig},
+    error::OidcError,
+    Result,
+};
+
+// Refresh id-tokens that expire within this window, like client-go.
+const EXPIRY_DELTA_SECONDS: i64 = 10;
+
+/// An `oidc` auth-provider, refreshing its id-token from the issuer with the refresh-token
+pub(crate) struct Oidc {
+    id_token: Option<String>,
+    expiry: Option<DateTime<Utc>>,
+    refresh_token: Option<String>,
+    issuer_url: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    // DER encoded roots to trust for the issuer
+    idp_certificate_authority: Option<Vec<Vec<u8>>>,
+}
+
+impl std::fmt::Debug for Oidc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Oidc")
+            .field("expiry", &self.expiry)
+            .field("issuer_url", &self.issuer_url)
+            .field("client_id", &self.client_id)
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    token_endpoint: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+    refresh_token: Option<String>,
+}
+
+impl Oidc {
+    pub(crate) fn from_provider(provider: &AuthProviderConfig) -> Result<Self> {
+        let config = |key: &str| provider.config.get(key).filter(|v| !v.is_empty()).cloned();
+        let id_token = config("id-token");
+        let refresh_token = config("refresh-token");
+        if id_token.is_none() && refresh_token.is_none() {
+            return Err(OidcError::MissingIdToken.into());
+        }
+        let expiry = id_token.as_deref().map(jwt_expiry).transpose()?.flatten();
+        let ca_data = config("idp-certificate-authority-data");
+        let ca_file = config("idp-certificate-authority");
+        let idp_certificate_authority = if ca_data.is_some() || ca_file.is_some() {
+            Some(certs(&data_or_file_with_base64(&ca_data, &ca_file)?))
+        } else {
+            None
+        };
+        Ok(Self {
+            id_token,
+            expiry,
+            refresh_token,
+            issuer_url: config("idp-issuer-url"),
+            client_id: config("client-id"),
+            client_secret: config("client-secret"),
+            idp_certificate_authority,
+        })
+    }
+
+    fn is_expiring(&self) -> bool {
+        match (&self.id_token, self.expiry) {
+            (None, _) => true,
+            (Some(_), Some(expiry)) => Utc::now() + Duration::seconds(EXPIRY_DELTA_SECONDS) >= expiry,
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Forces a refresh on the next call to [`Oidc::token`]
+    pub(crate) fn expire(&mut self) {
+        if self.refresh_token.is_some() {
+            self.expiry = Some(Utc::now());
+        }
+    }
+
+    /// Returns the id-token, refreshing it first if it has expired
+    pub(crate) async fn token(&mut self) -> Result<String> {
+        if self.is_expiring() {
+            self.refresh().await?;
+        }
+        Ok(self.id_token.clone().expect("id-token is set after refresh"))
+    }
+
+    async fn refresh(&mut self) -> Result<()> {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        #[cfg(feature = "native-tls")]
+        let connector = hyper_tls::HttpsConnector::from((
+            http,
+            tokio_native_tls::TlsConnector::from(crate::client::tls::native_tls::native_tls_connector(
+                None,
+                self.idp_certificate_authority.as_ref(),
+                false,
+                &[],
+            )?),
+        ));
+        #[cfg(all(not(feature = "native-tls"), feature = "rustls-tls"))]
+        let connector = match &self.idp_certificate_authority {
+            Some(roots) => hyper_rustls::HttpsConnector::from((
+                http,
+                std::sync::Arc::new(crate::client::tls::rustls_tls::rustls_client_config(
+                    None,
+                    Some(roots),
+                    false,
+                    &[],
+                )?),
+            )),
+            None => hyper_rustls::HttpsConnector::with_native_roots(),
+        };
+        #[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+        let connector = http;
+        self.refresh_with(&hyper::Client::builder().build(connector))
+            .await
+    }
+
+    async fn refresh_with<C>(&mut self, client: &hyper::Client<C, Body>) -> Result<()>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        let (refresh_token, issuer_url, client_id) =
+            match (&self.refresh_token, &self.issuer_url, &self.client_id) {
+                (Some(r), Some(i), Some(c)) => (r, i, c),
+                _ => return Err(OidcError::MissingRefreshConfig.into()),
+            };
+        tracing::debug!("refreshing oidc id-token from {}", issuer_url);
+
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer_url.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata =
+            send(client, http::Request::get(&discovery_url).body(Body::empty())?).await?;
+
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", client_id),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let request = http::Request::post(&metadata.token_endpoint)
+            .header(http::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form_urlencode(&form)))?;
+        let response: TokenResponse = send(client, request).await?;
+
+        let id_token = response.id_token.ok_or(OidcError::NoIdTokenInResponse)?;
+        self.expiry = jwt_expiry(&id_token)?;
+        self.id_token = Some(id_token);
+        // Issuers may rotate the refresh-token
+        if let Some(refresh_token) = response.refresh_token {
+            self.refresh_token = Some(refresh_token);
+        }
+        Ok(())
+    }
+}
+
+async fn send<C, T>(client: &hyper::Client<C, Body>, request: http::Request<Body>) -> Result<T>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    T: serde::de::DeserializeOwned,
+{
+    let url = request.uri().to_string();
+    let response = client.request(request).await.map_err(OidcError::Request)?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(OidcError::HttpStatus { url, status }.into());
+    }
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(OidcError::Request)?;
+    Ok(serde_json::from_slice(&body).map_err(OidcError::ParseResponse)?)
+}
+
+/// Reads the `exp` claim of a JWT without verifying it, since it is only used to decide when to refresh
+fn jwt_expiry(token: &str) -> Result<Option<DateTime<Utc>>> {
+    let invalid = |msg: &str| OidcError::InvalidIdToken(msg.to_string());
+    let payload = token.split('.').nth(1).ok_or_else(|| invalid("not a jwt"))?;
+    let payload = base64::decode_config(payload.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+        .map_err(|_| invalid("payload is not base64url encoded"))?;
+    let claims: HashMap<String, serde_json::Value> =
+        serde_json::from_slice(&payload).map_err(|_| invalid("payload is not a json object"))?;
+    Ok(claims
+        .get("exp")
+        .and_then(serde_json::Value::as_i64)
+        .and_then(|exp| Utc.timestamp_opt(exp, 0).single()))
+}
+
+fn form_urlencode(pairs: &[(&str, &str)]) -> String {
+    let encode = |s: &str| -> String {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (b as char).to_string()
+                }
+                b' ' => "+".to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    };
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", encode(k), encode(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Request, Response, Server,
+    };
+
+    fn jwt(exp: i64) -> String {
+        let claims = serde_json::json!({ "iss": "test", "exp": exp });
+        format!(
+            "e30.{}.c2ln",
+            base64::encode_config(serde_json::to_vec(&claims).unwrap(), base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    fn provider(config: &[(&str, &str)]) -> AuthProviderConfig {
+        AuthProviderConfig {
+            name: "oidc".into(),
+            config: config
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            other: Default::default(),
+        }
+    }
+
+    // Stand-in identity provider serving discovery and a refresh-token grant
+    async fn serve_issuer(new_id_token: String) -> SocketAddr {
+        let make_svc = make_service_fn(move |_| {
+            let new_id_token = new_id_token.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let new_id_token = new_id_token.clone();
+                    async move {
+                        let addr = req.headers()["host"].to_str().unwrap().to_string();
+                        let body = match req.uri().path() {
+                            "/dex/.well-known/openid-configuration" => serde_json::json!({
+                                "issuer": format!("http://{}/dex", addr),
+                                "token_endpoint": format!("http://{}/dex/token", addr),
+                            }),
+                            "/dex/token" => {
+                                let form = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                                let form = String::from_utf8(form.to_vec()).unwrap();
+                                assert!(form.contains("grant_type=refresh_token"));
+                                assert!(form.contains("refresh_token=old-refresh"));
+                                assert!(form.contains("client_id=kube"));
+                                assert!(form.contains("client_secret=s3cr%2Bt"));
+                                serde_json::json!({
+                                    "access_token": "unused",
+                                    "id_token": new_id_token,
+                                    "refresh_token": "new-refresh",
+                                })
+                            }
+                            _ => {
+                                return Ok::<_, Infallible>(
+                                    Response::builder().status(404).body(Body::empty()).unwrap(),
+                                )
+                            }
+                        };
+                        Ok(Response::new(Body::from(serde_json::to_vec(&body).unwrap())))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[test]
+    fn reads_jwt_expiry() {
+        assert_eq!(
+            jwt_expiry(&jwt(1_600_000_000)).unwrap(),
+            Utc.timestamp_opt(1_600_000_000, 0).single()
+        );
+        assert!(jwt_expiry("not-a-jwt").is_err());
+    }
+
+    #[tokio::test]
+    async fn valid_token_is_not_refreshed() {
+        let token = jwt((Utc::now() + Duration::hours(1)).timestamp());
+        let mut oidc = Oidc::from_provider(&provider(&[("id-token", &token)])).unwrap();
+        assert_eq!(oidc.token().await.unwrap(), token);
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_refreshed() {
+        let new_token = jwt((Utc::now() + Duration::hours(1)).timestamp());
+        let addr = serve_issuer(new_token.clone()).await;
+        let issuer = format!("http://{}/dex/", addr);
+        let expired = jwt((Utc::now() - Duration::minutes(1)).timestamp());
+        let mut oidc = Oidc::from_provider(&provider(&[
+            ("id-token", &expired),
+            ("refresh-token", "old-refresh"),
+            ("idp-issuer-url", &issuer),
+            ("client-id", "kube"),
+            ("client-secret", "s3cr+t"),
+        ]))
+        .unwrap();
+
+        assert_eq!(oidc.token().await.unwrap(), new_token);
+        assert_eq!(oidc.refresh_token.as_deref(), Some("new-refresh"));
+        // Now valid, so no further requests are made
+        assert_eq!(oidc.token().await.unwrap(), new_token);
+    }
+
+    #[tokio::test]
+    async fn expired_token_without_refresh_config_fails() {
+        let expired = jwt((Utc::now() - Duration::minutes(1)).timestamp());
+        let mut oidc = Oidc::from_provider(&provider(&[("id-token", &expired)])).unwrap();
+        assert!(oidc.token().await.is_err());
+    }
+}
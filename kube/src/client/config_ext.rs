@@ -5,7 +5,10 @@ use tower::util::Either;
 #[cfg(any(feature = "native-tls", feature = "rustls-tls"))] use super::tls;
 use super::{
     auth::Auth,
-    middleware::{AddAuthorizationLayer, AuthLayer, BaseUriLayer, RefreshTokenLayer},
+    middleware::{
+        AddAuthorizationLayer, AuthLayer, BaseUriLayer, ImpersonateLayer, RateLimitLayer, RefreshTokenLayer,
+        RetryLayer,
+    },
 };
 use crate::{Config, Result};
 
@@ -21,6 +24,19 @@ pub trait ConfigExt: private::Sealed {
     /// Optional layer to set up `Authorization` header depending on the config.
     fn auth_layer(&self) -> Result<Option<AuthLayer>>;
 
+    /// Optional layer to set up `Impersonate-*` headers depending on the config.
+    fn impersonate_layer(&self) -> Result<Option<ImpersonateLayer>>;
+
+    /// Optional layer to apply the client-side rate limit of the config.
+    ///
+    /// Each layer has its own token bucket, so build it once per [`Client`](crate::Client).
+    fn rate_limit_layer(&self) -> Option<RateLimitLayer>;
+
+    /// Optional layer to retry transient failures of idempotent requests depending on the config.
+    ///
+    /// Place it above [`ConfigExt::rate_limit_layer`] so that retries are rate limited as well.
+    fn retry_layer(&self) -> Option<RetryLayer>;
+
     /// Create [`hyper_tls::HttpsConnector`] based on config.
     ///
     /// # Example
@@ -100,6 +116,20 @@ pub trait ConfigExt: private::Sealed {
     fn rustls_client_config(&self) -> Result<rustls::ClientConfig>;
 }
 
+// Layer to set up `Authorization` header for an already loaded `Auth`
+pub(crate) fn auth_layer_for(auth: Auth) -> Option<AuthLayer> {
+    match auth {
+        Auth::None => None,
+        Auth::Basic(user, pass) => Some(AuthLayer(Either::A(
+            AddAuthorizationLayer::basic(&user, &pass).as_sensitive(true),
+        ))),
+        Auth::Bearer(token) => Some(AuthLayer(Either::A(
+            AddAuthorizationLayer::bearer(&token).as_sensitive(true),
+        ))),
+        Auth::RefreshableToken(r) => Some(AuthLayer(Either::B(RefreshTokenLayer::new(r)))),
+    }
+}
+
 mod private {
     pub trait Sealed {}
     impl Sealed for super::Config {}
@@ -111,16 +141,19 @@ impl ConfigExt for Config {
     }
 
     fn auth_layer(&self) -> Result<Option<AuthLayer>> {
-        Ok(match Auth::try_from(&self.auth_info)? {
-            Auth::None => None,
-            Auth::Basic(user, pass) => Some(AuthLayer(Either::A(
-                AddAuthorizationLayer::basic(&user, &pass).as_sensitive(true),
-            ))),
-            Auth::Bearer(token) => Some(AuthLayer(Either::A(
-                AddAuthorizationLayer::bearer(&token).as_sensitive(true),
-            ))),
-            Auth::RefreshableToken(r) => Some(AuthLayer(Either::B(RefreshTokenLayer::new(r)))),
-        })
+        Ok(auth_layer_for(Auth::try_from(&self.auth_info)?))
+    }
+
+    fn impersonate_layer(&self) -> Result<Option<ImpersonateLayer>> {
+        self.impersonation.as_ref().map(ImpersonateLayer::new).transpose()
+    }
+
+    fn rate_limit_layer(&self) -> Option<RateLimitLayer> {
+        self.rate_limit.map(RateLimitLayer::new)
+    }
+
+    fn retry_layer(&self) -> Option<RetryLayer> {
+        self.retry.map(RetryLayer::new)
     }
 
     #[cfg(feature = "native-tls")]
@@ -129,6 +162,7 @@ impl ConfigExt for Config {
             self.identity_pem.as_ref(),
             self.root_cert.as_ref(),
             self.accept_invalid_certs,
+            &[],
         )
     }
 
@@ -146,6 +180,7 @@ impl ConfigExt for Config {
             self.identity_pem.as_ref(),
             self.root_cert.as_ref(),
             self.accept_invalid_certs,
+            &[],
         )
     }
 
@@ -0,0 +1,328 @@
+//! Send mutating requests as dry runs, recording what they would have changed.
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    Method, Request, Response, StatusCode, Uri,
+};
+use hyper::Body;
+use serde_json::Value;
+use tower::{BoxError, Layer, Service, ServiceExt};
+
+use crate::client::body::BodyStreamExt;
+
+/// A mutation that was sent as a dry run by [`DryRun`].
+#[derive(Clone, Debug)]
+pub struct Mutation {
+    /// HTTP method of the request
+    pub method: Method,
+    /// Path and query of the request, without `dryRun`
+    pub uri: String,
+    /// Request body, like the object to create or the patch
+    pub body: Option<Value>,
+    /// Object at the path of the request before the mutation, if any
+    ///
+    /// For a collection, this is the list of objects matching the selectors of the request.
+    /// Not fetched for `POST`, which creates objects.
+    pub before: Option<Value>,
+    /// Status of the dry run response
+    pub status: StatusCode,
+    /// Object the apiserver would have stored, from the dry run response
+    pub after: Option<Value>,
+}
+
+/// Layer that applies [`DryRun`] which sends mutating requests with `dryRun=All`.
+///
+/// Services created by the same layer (and their clones) record to the same list of [`Mutation`]s.
+/// Use [`Client::dry_run`](crate::Client::dry_run) to add it to an existing client.
+///
+/// ```no_run
+/// # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+/// use kube::{client::middleware::DryRunLayer, Client};
+///
+/// let audit = DryRunLayer::new();
+/// let client = Client::try_default().await?.dry_run(&audit);
+/// // run a reconciler with `client`
+/// for mutation in audit.mutations() {
+///     println!("{} {}", mutation.method, mutation.uri);
+///     println!("before: {}", mutation.before.unwrap_or_default());
+///     println!("after: {}", mutation.after.unwrap_or_default());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DryRunLayer {
+    mutations: Arc<Mutex<Vec<Mutation>>>,
+}
+
+impl DryRunLayer {
+    /// Create a layer with no recorded mutations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The mutations sent so far, in the order of their responses.
+    pub fn mutations(&self) -> Vec<Mutation> {
+        self.mutations.lock().expect("dry run lock poisoned").clone()
+    }
+}
+
+impl<S> Layer<S> for DryRunLayer {
+    type Service = DryRun<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DryRun {
+            mutations: self.mutations.clone(),
+            inner,
+        }
+    }
+}
+
+/// Middleware that adds `dryRun=All` to `POST`, `PUT`, `PATCH` and `DELETE` requests and records them.
+///
+/// Before sending a `PUT`, `PATCH` or `DELETE`, the object at its path is fetched with a `GET`
+/// to record the state before the mutation. Request and response bodies of mutations are buffered.
+#[derive(Clone, Debug)]
+pub struct DryRun<S> {
+    mutations: Arc<Mutex<Vec<Mutation>>>,
+    inner: S,
+}
+
+impl<S, ResBody> Service<Request<Body>> for DryRun<S>
+where
+    S: Service<Request<Body>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Pass on the service that was driven to readiness, like `RefreshToken`.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let mutations = self.mutations.clone();
+        Box::pin(async move {
+            if !matches!(
+                *req.method(),
+                Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+            ) {
+                let res = inner.call(req).await.map_err(Into::into)?;
+                return Ok(res.map(|body| Body::wrap_stream(body.into_stream())));
+            }
+
+            let (mut parts, body) = req.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            let before = if parts.method == Method::POST {
+                None
+            } else {
+                let mut get = Request::get(before_uri(&parts.uri)?).body(Body::empty())?;
+                *get.headers_mut() = parts.headers.clone();
+                get.headers_mut().remove(CONTENT_TYPE);
+                get.headers_mut().remove(CONTENT_LENGTH);
+                let res = inner.call(get).await.map_err(Into::into)?;
+                inner.ready().await.map_err(Into::into)?;
+                if res.status().is_success() {
+                    json(&hyper::body::to_bytes(res.into_body()).await.map_err(Into::into)?)
+                } else {
+                    None
+                }
+            };
+
+            let uri = parts.uri.path_and_query().map_or("", |pq| pq.as_str()).to_owned();
+            parts.uri = with_dry_run(&parts.uri)?;
+            let method = parts.method.clone();
+            let res = inner
+                .call(Request::from_parts(parts, Body::from(body.clone())))
+                .await
+                .map_err(Into::into)?;
+            let (parts, res_body) = res.into_parts();
+            let res_body = hyper::body::to_bytes(res_body).await.map_err(Into::into)?;
+            tracing::debug!(%method, %uri, status = %parts.status, "sent mutation as dry run");
+            let mutation = Mutation {
+                method,
+                uri,
+                body: json(&body),
+                before,
+                status: parts.status,
+                after: json(&res_body).filter(|_| parts.status.is_success()),
+            };
+            mutations.lock().expect("dry run lock poisoned").push(mutation);
+            Ok(Response::from_parts(parts, Body::from(res_body)))
+        })
+    }
+}
+
+fn json(body: &[u8]) -> Option<Value> {
+    serde_json::from_slice(body).ok()
+}
+
+// The uri to `GET` the state before a mutation
+//
+// Objects are fetched without the query of the mutation. Collections, which are deleted with
+// `deletecollection`, keep their query, so that the same selectors list the objects being deleted.
+fn before_uri(uri: &Uri) -> Result<Uri, http::Error> {
+    let path_and_query = if is_collection(uri.path()) {
+        let query = uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| {
+                let key = pair.split('=').next().unwrap_or_default();
+                !pair.is_empty() && !matches!(key, "dryRun" | "fieldManager" | "propagationPolicy")
+            })
+            .collect::<Vec<_>>();
+        if query.is_empty() {
+            uri.path().to_owned()
+        } else {
+            format!("{}?{}", uri.path(), query.join("&"))
+        }
+    } else {
+        uri.path().to_owned()
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
+// Whether a path is a collection of resources rather than a named object,
+// like `/apis/apps/v1/namespaces/ns/deployments` and `/api/v1/namespaces`.
+fn is_collection(path: &str) -> bool {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    let resource = match segments.as_slice() {
+        ["api", _version, rest @ ..] | ["apis", _, _version, rest @ ..] => rest,
+        _ => return false,
+    };
+    let resource = match resource {
+        ["namespaces", _namespace, rest @ ..] if !rest.is_empty() => rest,
+        resource => resource,
+    };
+    resource.len() == 1
+}
+
+// Replaces any `dryRun` query parameter with `dryRun=All`
+fn with_dry_run(uri: &Uri) -> Result<Uri, http::Error> {
+    let mut query = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !pair.starts_with("dryRun="))
+        .collect::<Vec<_>>();
+    query.push("dryRun=All");
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(format!("{}?{}", uri.path(), query.join("&")).parse()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn sets_dry_run_query() {
+        let uri = |s: &str| s.parse::<Uri>().unwrap();
+        assert_eq!(
+            with_dry_run(&uri("/api/v1/namespaces/ns/pods")).unwrap(),
+            uri("/api/v1/namespaces/ns/pods?dryRun=All")
+        );
+        assert_eq!(
+            with_dry_run(&uri("https://k8s:6443/api/v1/pods/p?fieldManager=a&dryRun=")).unwrap(),
+            uri("https://k8s:6443/api/v1/pods/p?fieldManager=a&dryRun=All")
+        );
+        assert_eq!(
+            before_uri(&uri("https://k8s:6443/api/v1/namespaces/ns/pods/p?dryRun=All")).unwrap(),
+            uri("https://k8s:6443/api/v1/namespaces/ns/pods/p")
+        );
+        assert_eq!(
+            before_uri(&uri(
+                "/apis/apps/v1/namespaces/ns/deployments?labelSelector=app%3Da&fieldSelector=a&dryRun=All"
+            ))
+            .unwrap(),
+            uri("/apis/apps/v1/namespaces/ns/deployments?labelSelector=app%3Da&fieldSelector=a")
+        );
+        assert_eq!(
+            before_uri(&uri("/api/v1/namespaces?dryRun=All")).unwrap(),
+            uri("/api/v1/namespaces")
+        );
+        assert_eq!(
+            before_uri(&uri("/api/v1/namespaces/ns?propagationPolicy=Foreground")).unwrap(),
+            uri("/api/v1/namespaces/ns")
+        );
+    }
+
+    #[tokio::test]
+    async fn records_mutations_as_dry_runs() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let service = tower::service_fn({
+            let requests = requests.clone();
+            move |req: Request<Body>| {
+                requests
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", req.method(), req.uri()));
+                let body = match *req.method() {
+                    Method::GET => json!({ "metadata": { "name": "p", "labels": { "app": "a" } } }),
+                    _ => json!({ "metadata": { "name": "p", "labels": { "app": "b" } } }),
+                };
+                async move { Response::builder().body(Body::from(body.to_string())) }
+            }
+        });
+        let layer = DryRunLayer::new();
+        let client = crate::Client::new(service, "default").dry_run(&layer);
+
+        let patch = Request::patch("/api/v1/namespaces/default/pods/p?fieldManager=test")
+            .header(CONTENT_TYPE, "application/merge-patch+json")
+            .body(br#"{"metadata":{"labels":{"app":"b"}}}"#.to_vec())
+            .unwrap();
+        let patched: Value = client.request(patch).await.unwrap();
+        assert_eq!(patched["metadata"]["labels"]["app"], "b");
+        client
+            .request_text(
+                Request::get("/api/v1/namespaces/default/pods")
+                    .body(vec![])
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(*requests.lock().unwrap(), vec![
+            "GET /api/v1/namespaces/default/pods/p",
+            "PATCH /api/v1/namespaces/default/pods/p?fieldManager=test&dryRun=All",
+            "GET /api/v1/namespaces/default/pods",
+        ]);
+        let mutations = layer.mutations();
+        assert_eq!(mutations.len(), 1);
+        let mutation = &mutations[0];
+        assert_eq!(mutation.method, Method::PATCH);
+        assert_eq!(
+            mutation.uri,
+            "/api/v1/namespaces/default/pods/p?fieldManager=test"
+        );
+        assert_eq!(
+            mutation.body,
+            Some(json!({ "metadata": { "labels": { "app": "b" } } }))
+        );
+        assert_eq!(
+            mutation.before.as_ref().unwrap()["metadata"]["labels"]["app"],
+            "a"
+        );
+        assert_eq!(mutation.status, StatusCode::OK);
+        assert_eq!(mutation.after.as_ref().unwrap()["metadata"]["labels"]["app"], "b");
+    }
+}
@@ -0,0 +1,218 @@
+//! Set `Impersonate-*` headers of requests.
+use http::{header::HeaderName, HeaderValue, Request};
+use tower::{Layer, Service};
+
+use crate::{config::Impersonation, error::ConfigError, Result};
+
+const IMPERSONATE_USER: &str = "impersonate-user";
+const IMPERSONATE_GROUP: &str = "impersonate-group";
+const IMPERSONATE_EXTRA_PREFIX: &str = "impersonate-extra-";
+
+/// Layer that applies [`Impersonate`] which makes all requests act as another user.
+#[derive(Debug, Clone)]
+pub struct ImpersonateLayer {
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl ImpersonateLayer {
+    /// Impersonate the user, groups and extra fields in `impersonation`.
+    ///
+    /// Fails if any of the values cannot be used in a header.
+    pub fn new(impersonation: &Impersonation) -> Result<Self> {
+        let value = |v: &str| HeaderValue::from_str(v).map_err(ConfigError::InvalidImpersonation);
+        let mut headers = vec![(
+            HeaderName::from_static(IMPERSONATE_USER),
+            value(&impersonation.user)?,
+        )];
+        for group in &impersonation.groups {
+            headers.push((HeaderName::from_static(IMPERSONATE_GROUP), value(group)?));
+        }
+        for (key, values) in &impersonation.extra {
+            let name = format!("{}{}", IMPERSONATE_EXTRA_PREFIX, encode_extra_key(key));
+            let name = HeaderName::from_bytes(name.as_bytes()).expect("encoded header name is valid");
+            for v in values {
+                headers.push((name.clone(), value(v)?));
+            }
+        }
+        Ok(Self { headers })
+    }
+}
+
+impl<S> Layer<S> for ImpersonateLayer {
+    type Service = Impersonate<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Impersonate {
+            headers: self.headers.clone(),
+            inner,
+        }
+    }
+}
+
+/// Middleware that sets `Impersonate-*` headers.
+///
+/// Requests that already impersonate a user, like those of [`Client::impersonate`](crate::Client::impersonate)
+/// going through the impersonation of the [`Config`](crate::Config), are left alone so that the outermost layer wins.
+/// Otherwise any partial `Impersonate-*` headers are replaced.
+#[derive(Debug, Clone)]
+pub struct Impersonate<S> {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    inner: S,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for Impersonate<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Error = S::Error;
+    type Future = S::Future;
+    type Response = S::Response;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let headers = req.headers_mut();
+        if headers.contains_key(IMPERSONATE_USER) {
+            return self.inner.call(req);
+        }
+        let previous = headers
+            .keys()
+            .filter(|name| name.as_str().starts_with("impersonate-"))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in previous {
+            headers.remove(name);
+        }
+        for (name, value) in &self.headers {
+            headers.append(name.clone(), value.clone());
+        }
+        self.inner.call(req)
+    }
+}
+
+// Extra keys are percent-encoded so that they are valid in header names, as expected by the apiserver.
+fn encode_extra_key(key: &str) -> String {
+    key.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-!#$&'*+.^_`|~".contains(&b) {
+                (b.to_ascii_lowercase() as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::pin_mut;
+    use http::{Request, Response};
+    use hyper::Body;
+    use tower::ServiceExt;
+    use tower_test::mock;
+
+    #[tokio::test]
+    async fn sets_impersonation_headers() {
+        let mut impersonation = Impersonation::new("system:serviceaccount:default:sa", vec!["devs", "ops"]);
+        impersonation
+            .extra
+            .insert("scopes.example.com/Tenant".into(), vec!["a".into()]);
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.expect("service not called");
+            let headers = request.headers();
+            assert_eq!(headers["impersonate-user"], "system:serviceaccount:default:sa");
+            let groups = headers.get_all("impersonate-group").iter().collect::<Vec<_>>();
+            assert_eq!(groups, vec!["devs", "ops"]);
+            assert_eq!(headers["impersonate-extra-scopes.example.com%2Ftenant"], "a");
+            assert!(!headers.contains_key("impersonate-extra-stale"));
+            send.send_response(Response::builder().body(Body::empty()).unwrap());
+        });
+
+        let service = ImpersonateLayer::new(&impersonation).unwrap().layer(mock_service);
+        let request = Request::builder()
+            .uri("/")
+            .header("impersonate-extra-stale", "x")
+            .body(Body::empty())
+            .unwrap();
+        service.oneshot(request).await.unwrap();
+        spawned.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_impersonation_of_outer_layers() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.expect("service not called");
+            let headers = request.headers();
+            assert_eq!(headers["impersonate-user"], "outer");
+            let groups = headers.get_all("impersonate-group").iter().collect::<Vec<_>>();
+            assert_eq!(groups, vec!["outer-group"]);
+            send.send_response(Response::builder().body(Body::empty()).unwrap());
+        });
+
+        let inner = ImpersonateLayer::new(&Impersonation::new("inner", vec!["inner-group"])).unwrap();
+        let outer = ImpersonateLayer::new(&Impersonation::new("outer", vec!["outer-group"])).unwrap();
+        let service = outer.layer(inner.layer(mock_service));
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        service.oneshot(request).await.unwrap();
+        spawned.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn client_impersonation_overrides_config() {
+        use std::convert::TryFrom;
+
+        use hyper::{server::conn::Http, service::service_fn};
+        use k8s_openapi::api::core::v1::Pod;
+        use tokio::net::TcpListener;
+
+        use crate::{Api, Client, Config};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|req: Request<Body>| async move {
+                let headers = req.headers();
+                assert_eq!(
+                    headers["impersonate-user"],
+                    "system:serviceaccount:default:reader"
+                );
+                let groups = headers.get_all("impersonate-group").iter().collect::<Vec<_>>();
+                assert_eq!(groups, vec!["system:serviceaccounts"]);
+                assert!(!headers.contains_key("impersonate-extra-reason"));
+                let pod =
+                    serde_json::json!({ "apiVersion": "v1", "kind": "Pod", "metadata": { "name": "p" } });
+                Ok::<_, hyper::Error>(Response::new(Body::from(pod.to_string())))
+            });
+            Http::new().serve_connection(stream, service).await.unwrap();
+        });
+
+        let mut config = Config::new(format!("http://{}", addr).parse().unwrap());
+        let mut impersonation = Impersonation::new("admin", vec!["system:masters"]);
+        impersonation.extra.insert("reason".into(), vec!["config".into()]);
+        config.impersonation = Some(impersonation);
+        let client = Client::try_from(config)
+            .unwrap()
+            .impersonate("system:serviceaccount:default:reader", &[
+                "system:serviceaccounts",
+            ])
+            .unwrap();
+        let pods: Api<Pod> = Api::default_namespaced(client);
+        pods.get("p").await.unwrap();
+        drop(pods);
+        server.abort();
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(ImpersonateLayer::new(&Impersonation::new("bad\nuser", Vec::<String>::new())).is_err());
+    }
+}
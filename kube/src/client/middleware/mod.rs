@@ -3,10 +3,18 @@ use tower::{util::Either, Layer};
 pub(crate) use tower_http::auth::AddAuthorizationLayer;
 
 mod base_uri;
+mod dry_run;
+mod impersonate;
+mod rate_limit;
 mod refresh_token;
+mod retry;
 
 pub use base_uri::{BaseUri, BaseUriLayer};
+pub use dry_run::{DryRun, DryRunLayer, Mutation};
+pub use impersonate::{Impersonate, ImpersonateLayer};
+pub use rate_limit::{RateLimitLayer, RateLimiter};
 pub(crate) use refresh_token::RefreshTokenLayer;
+pub use retry::{Retry, RetryLayer};
 /// Layer to set up `Authorization` header depending on the config.
 pub struct AuthLayer(pub(crate) Either<AddAuthorizationLayer, RefreshTokenLayer>);
 
@@ -0,0 +1,267 @@
+//! Client-side rate limiting of requests.
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http::Request;
+use tokio::time::Instant;
+use tower::{Layer, Service};
+
+use crate::config::RateLimit;
+
+/// Layer that applies [`RateLimiter`] which delays requests exceeding a [`RateLimit`].
+///
+/// Services created by the same layer (and their clones) share one token bucket.
+///
+/// ```no_run
+/// use kube::{client::middleware::RateLimitLayer, config::RateLimit};
+/// use tower::ServiceBuilder;
+/// # fn wrap<S>(service: S) {
+/// let service = ServiceBuilder::new()
+///     .layer(RateLimitLayer::new(RateLimit::new(20.0, 50).exempt_watches()))
+///     .service(service);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    bucket: Arc<Mutex<TokenBucket>>,
+    exempt_watches: bool,
+}
+
+impl RateLimitLayer {
+    /// Rate limit requests with `limit`.
+    ///
+    /// A non-positive `qps` disables rate limiting.
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(TokenBucket::new(limit.qps, limit.burst))),
+            exempt_watches: limit.exempt_watches,
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimiter<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimiter {
+            bucket: self.bucket.clone(),
+            exempt_watches: self.exempt_watches,
+            inner,
+        }
+    }
+}
+
+/// Middleware that waits for a token from a shared bucket before sending each request.
+#[derive(Debug, Clone)]
+pub struct RateLimiter<S> {
+    bucket: Arc<Mutex<TokenBucket>>,
+    exempt_watches: bool,
+    inner: S,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RateLimiter<S>
+where
+    S: Service<Request<ReqBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+    type Response = S::Response;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let reservation = if self.exempt_watches && is_watch(&req) {
+            None
+        } else {
+            Some(Reservation::take(&self.bucket))
+        };
+        // Pass on the service that was driven to readiness, like `RefreshToken`.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            if let Some(reservation) = reservation {
+                if let Some(delay) = reservation.delay {
+                    tracing::trace!(?delay, "waiting for client-side rate limit");
+                    tokio::time::sleep(delay).await;
+                }
+                reservation.spend();
+            }
+            inner.call(req).await
+        })
+    }
+}
+
+pub(super) fn is_watch<B>(req: &Request<B>) -> bool {
+    req.uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .any(|pair| pair == "watch=true" || pair == "watch=1")
+}
+
+// A token taken from the bucket, which is returned unless the request is sent
+struct Reservation {
+    bucket: Arc<Mutex<TokenBucket>>,
+    delay: Option<Duration>,
+    spent: bool,
+}
+
+impl Reservation {
+    fn take(bucket: &Arc<Mutex<TokenBucket>>) -> Self {
+        let delay = bucket.lock().expect("rate limit lock poisoned").reserve();
+        Self {
+            bucket: bucket.clone(),
+            delay,
+            spent: false,
+        }
+    }
+
+    fn spend(mut self) {
+        self.spent = true;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.spent {
+            self.bucket.lock().expect("rate limit lock poisoned").refund();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    qps: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(qps: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            qps,
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    // Takes a token, returning how long to wait for it when the bucket is empty.
+    // Tokens can be borrowed ahead of time so that waiting requests are served in order.
+    fn reserve(&mut self) -> Option<Duration> {
+        if self.qps <= 0.0 {
+            return None;
+        }
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.qps).min(self.burst) - 1.0;
+        self.last = now;
+        if self.tokens >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-self.tokens / self.qps))
+        }
+    }
+
+    // Returns a token of a request that was abandoned before it was sent
+    fn refund(&mut self) {
+        if self.qps > 0.0 {
+            self.tokens = (self.tokens + 1.0).min(self.burst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use http::Response;
+    use hyper::Body;
+    use tower::ServiceExt;
+
+    #[test]
+    fn bucket_allows_burst_then_waits() {
+        let mut bucket = TokenBucket::new(10.0, 2);
+        assert_eq!(bucket.reserve(), None);
+        assert_eq!(bucket.reserve(), None);
+        let wait = bucket.reserve().unwrap();
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+        // Later requests queue up behind the previous ones
+        let wait = bucket.reserve().unwrap();
+        assert!(wait > Duration::from_millis(190) && wait <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn abandoned_reservations_are_refunded() {
+        let bucket = Arc::new(Mutex::new(TokenBucket::new(10.0, 1)));
+        let first = Reservation::take(&bucket);
+        assert_eq!(first.delay, None);
+        first.spend();
+        // Dropping a reservation before it is spent gives its token back
+        let delayed = Reservation::take(&bucket);
+        assert!(delayed.delay.is_some());
+        drop(delayed);
+        Reservation::take(&bucket).spend();
+        let delay = Reservation::take(&bucket).delay.unwrap();
+        assert!(delay > Duration::from_millis(190) && delay <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn non_positive_qps_is_unlimited() {
+        let mut bucket = TokenBucket::new(0.0, 1);
+        for _ in 0..10 {
+            assert_eq!(bucket.reserve(), None);
+        }
+    }
+
+    #[test]
+    fn detects_watch_requests() {
+        let req = |uri: &str| Request::get(uri).body(()).unwrap();
+        assert!(is_watch(&req("/api/v1/pods?watch=true&resourceVersion=1")));
+        assert!(is_watch(&req("/api/v1/pods?resourceVersion=1&watch=1")));
+        assert!(!is_watch(&req("/api/v1/pods?watch=false")));
+        assert!(!is_watch(&req("/api/v1/pods")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delays_requests_and_exempts_watches() {
+        let service = tower::service_fn(|_: Request<Body>| async {
+            Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+        });
+        let layer = RateLimitLayer::new(RateLimit::new(10.0, 1).exempt_watches());
+        let request = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let start = Instant::now();
+        layer
+            .layer(service)
+            .oneshot(request("/api/v1/pods"))
+            .await
+            .unwrap();
+        layer
+            .layer(service)
+            .oneshot(request("/api/v1/pods?watch=true"))
+            .await
+            .unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        // Services from the same layer share the bucket, so this waits for the next token
+        layer
+            .layer(service)
+            .oneshot(request("/api/v1/pods"))
+            .await
+            .unwrap();
+        assert!(
+            start.elapsed() >= Duration::from_millis(100) && start.elapsed() < Duration::from_millis(110)
+        );
+    }
+}
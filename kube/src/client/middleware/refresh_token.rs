@@ -4,14 +4,14 @@ use std::{
 };
 
 use futures::{ready, Future};
-use http::{header::AUTHORIZATION, Request, Response};
+use http::{header::AUTHORIZATION, Request, Response, StatusCode};
 use pin_project::pin_project;
 use tower::{layer::Layer, BoxError, Service};
 
 use crate::{client::auth::RefreshableToken, Result};
 
 /// `Layer` to decorate the request with `Authorization` header with refreshable token.
-/// Token is refreshed automatically when necessary.
+/// Token is refreshed automatically when necessary, or after an `Unauthorized` response.
 pub struct RefreshTokenLayer {
     refreshable: RefreshableToken,
 }
@@ -33,6 +33,7 @@ impl<S> Layer<S> for RefreshTokenLayer {
     }
 }
 
+#[derive(Clone)]
 pub struct RefreshToken<S> {
     refreshable: RefreshableToken,
     service: S,
@@ -73,6 +74,7 @@ where
         RefreshTokenFuture {
             state: State::Request(Box::pin(request)),
             service,
+            refreshable: self.refreshable.clone(),
         }
     }
 }
@@ -97,11 +99,12 @@ where
     #[pin]
     state: State<RequestFuture<B>, S::Future>,
     service: S,
+    refreshable: RefreshableToken,
 }
 
-impl<S, B> Future for RefreshTokenFuture<S, B>
+impl<S, B, ResB> Future for RefreshTokenFuture<S, B>
 where
-    S: Service<Request<B>>,
+    S: Service<Request<B>, Response = Response<ResB>>,
     S::Error: Into<BoxError>,
     B: http_body::Body,
 {
@@ -119,7 +122,12 @@ where
                 }
 
                 StateProj::Response(response) => {
-                    return response.poll(cx).map_err(Into::into);
+                    let response = ready!(response.poll(cx)).map_err(Into::into)?;
+                    if response.status() == StatusCode::UNAUTHORIZED {
+                        // The token may have been rotated or revoked, so don't wait for it to expire
+                        this.refreshable.invalidate();
+                    }
+                    return Poll::Ready(Ok(response));
                 }
             }
         }
@@ -140,7 +148,7 @@ mod tests {
     use tokio_test::assert_ready_ok;
     use tower_test::{mock, mock::Handle};
 
-    use crate::{config::AuthInfo, error::ConfigError, Error};
+    use crate::{client::auth::TokenFile, config::AuthInfo, error::ConfigError, Error};
 
     #[tokio::test(flavor = "current_thread")]
     async fn valid_token() {
@@ -186,6 +194,42 @@ mod tests {
         ));
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn token_file_reloaded_after_unauthorized() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "first\n").unwrap();
+        let auth = RefreshableToken::File(Arc::new(Mutex::new(TokenFile::new(file.path()).unwrap())));
+        let (mut service, handle): (_, Handle<Request<hyper::Body>, Response<hyper::Body>>) =
+            mock::spawn_layer(RefreshTokenLayer::new(auth));
+
+        let path = file.path().to_path_buf();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.expect("service not called");
+            assert_eq!(request.headers().get(AUTHORIZATION).unwrap(), "Bearer first");
+            // The token is rotated and the old one is rejected
+            std::fs::write(&path, "second").unwrap();
+            send.send_response(
+                Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Body::empty())
+                    .unwrap(),
+            );
+            let (request, send) = handle.next_request().await.expect("service not called");
+            assert_eq!(request.headers().get(AUTHORIZATION).unwrap(), "Bearer second");
+            send.send_response(Response::builder().body(Body::empty()).unwrap());
+        });
+
+        for _ in 0..2 {
+            assert_ready_ok!(service.poll_ready());
+            service
+                .call(Request::builder().uri("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+        spawned.await.unwrap();
+    }
+
     fn test_token(token: String) -> RefreshableToken {
         let expiry = Utc::now() + Duration::seconds(60 * 60);
         let info = AuthInfo {
@@ -0,0 +1,342 @@
+//! Retry transient failures of idempotent requests.
+use std::{
+    error::Error as StdError,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http::{header::RETRY_AFTER, HeaderMap, Method, Request, Response, StatusCode};
+use hyper::Body;
+use tower::{BoxError, Layer, Service, ServiceExt};
+
+use super::rate_limit::is_watch;
+use crate::config::RetryPolicy;
+
+/// Layer that applies [`Retry`] which retries idempotent requests according to a [`RetryPolicy`].
+#[derive(Debug, Clone)]
+pub struct RetryLayer {
+    policy: RetryPolicy,
+}
+
+impl RetryLayer {
+    /// Retry requests with `policy`.
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+impl<S> Layer<S> for RetryLayer {
+    type Service = Retry<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Retry {
+            policy: self.policy,
+            inner,
+        }
+    }
+}
+
+/// Middleware that retries requests failing with `429`, `500`, `503` or a dropped connection.
+///
+/// Watch requests are never retried, since their callers already re-establish them.
+/// Request bodies are buffered so that they can be sent again.
+/// Request extensions are only passed on to the first attempt.
+#[derive(Debug, Clone)]
+pub struct Retry<S> {
+    policy: RetryPolicy,
+    inner: S,
+}
+
+impl<S, ResBody> Service<Request<Body>> for Retry<S>
+where
+    S: Service<Request<Body>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ResBody: Send + 'static,
+{
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+    type Response = S::Response;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Pass on the service that was driven to readiness, like `RefreshToken`.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let policy = self.policy;
+        Box::pin(async move {
+            let method = req.method();
+            let idempotent = method == Method::GET || (policy.retry_updates && method == Method::PUT);
+            if !idempotent || is_watch(&req) {
+                return inner.call(req).await.map_err(Into::into);
+            }
+
+            let (mut parts, body) = req.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            if parts.method == Method::PUT && !has_resource_version(&body) {
+                return inner
+                    .call(Request::from_parts(parts, Body::from(body)))
+                    .await
+                    .map_err(Into::into);
+            }
+
+            let mut extensions = Some(std::mem::take(&mut parts.extensions));
+            let mut attempt = 0;
+            loop {
+                if attempt > 0 {
+                    inner.ready().await.map_err(Into::into)?;
+                }
+                let mut req = Request::new(Body::from(body.clone()));
+                *req.method_mut() = parts.method.clone();
+                *req.uri_mut() = parts.uri.clone();
+                *req.version_mut() = parts.version;
+                *req.headers_mut() = parts.headers.clone();
+                if let Some(extensions) = extensions.take() {
+                    *req.extensions_mut() = extensions;
+                }
+
+                let result = inner.call(req).await.map_err(Into::into);
+                let retry_after = match &result {
+                    Ok(res) if is_retryable_status(res.status()) => retry_after(res.headers()),
+                    Err(err) if is_connection_error(&**err) => None,
+                    _ => return result,
+                };
+                if attempt >= policy.max_retries {
+                    return result;
+                }
+                let delay = retry_after
+                    .map(|delay| delay.min(policy.max_backoff))
+                    .unwrap_or_else(|| backoff(&policy, attempt));
+                tracing::debug!(?delay, attempt, "retrying request");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+// The apiserver sends `Retry-After` in seconds
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+fn backoff(policy: &RetryPolicy, attempt: u32) -> Duration {
+    policy
+        .initial_backoff
+        .checked_mul(2u32.saturating_pow(attempt))
+        .map_or(policy.max_backoff, |backoff| backoff.min(policy.max_backoff))
+}
+
+fn is_connection_error(err: &(dyn StdError + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<hyper::Error>() {
+            if err.is_connect() || err.is_incomplete_message() {
+                return true;
+            }
+        }
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            if matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe
+            ) {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+fn has_resource_version(body: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|obj| {
+            obj.pointer("/metadata/resourceVersion")?
+                .as_str()
+                .map(|rv| !rv.is_empty())
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        collections::VecDeque,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use tokio::time::Instant;
+
+    type Outcome = Result<Response<Body>, BoxError>;
+
+    // Responds with `failures` before succeeding, counting the attempts
+    #[derive(Clone)]
+    struct Flaky {
+        failures: Arc<Mutex<VecDeque<Outcome>>>,
+        attempts: Arc<AtomicUsize>,
+    }
+
+    impl Service<Request<Body>> for Flaky {
+        type Error = BoxError;
+        type Future = futures::future::Ready<Outcome>;
+        type Response = Response<Body>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Request<Body>) -> Self::Future {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let next = self.failures.lock().unwrap().pop_front();
+            futures::future::ready(next.unwrap_or_else(|| Ok(Response::new(Body::empty()))))
+        }
+    }
+
+    fn flaky(failures: Vec<Outcome>) -> (Flaky, Arc<AtomicUsize>) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let service = Flaky {
+            failures: Arc::new(Mutex::new(failures.into())),
+            attempts: attempts.clone(),
+        };
+        (service, attempts)
+    }
+
+    fn status(status: StatusCode) -> Outcome {
+        Ok(Response::builder().status(status).body(Body::empty()).unwrap())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_get_with_backoff_and_retry_after() {
+        let throttled = Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(RETRY_AFTER, "5")
+            .body(Body::empty())
+            .unwrap();
+        let (service, attempts) = flaky(vec![status(StatusCode::SERVICE_UNAVAILABLE), Ok(throttled)]);
+        let service = RetryLayer::new(RetryPolicy::default()).layer(service);
+
+        let start = Instant::now();
+        let res = service
+            .oneshot(Request::get("/api/v1/pods").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        // 500ms backoff after the 503, then the 5s from `Retry-After`
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(5500) && elapsed < Duration::from_millis(5600));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_after_is_capped_at_max_backoff() {
+        let throttled = Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(RETRY_AFTER, "3600")
+            .body(Body::empty())
+            .unwrap();
+        let (service, _) = flaky(vec![Ok(throttled)]);
+        let policy = RetryPolicy {
+            max_backoff: Duration::from_secs(2),
+            ..RetryPolicy::default()
+        };
+        let service = RetryLayer::new(policy).layer(service);
+
+        let start = Instant::now();
+        let res = service
+            .oneshot(Request::get("/api/v1/pods").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(2) && elapsed < Duration::from_millis(2100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_connection_resets() {
+        let reset: BoxError = Box::new(io::Error::from(io::ErrorKind::ConnectionReset));
+        let (service, attempts) = flaky(vec![Err(reset)]);
+        let service = RetryLayer::new(RetryPolicy::default()).layer(service);
+        let res = service
+            .oneshot(Request::get("/api/v1/pods").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_retries() {
+        let failures = (0..5)
+            .map(|_| status(StatusCode::INTERNAL_SERVER_ERROR))
+            .collect();
+        let (service, attempts) = flaky(failures);
+        let service = RetryLayer::new(RetryPolicy::new(2)).layer(service);
+        let res = service
+            .oneshot(Request::get("/api/v1/pods").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn only_retries_idempotent_requests() {
+        let put =
+            |body: &'static str| Request::put("/api/v1/namespaces/default/pods/p").body(Body::from(body));
+        let versioned = r#"{"metadata":{"name":"p","resourceVersion":"12"}}"#;
+        let unversioned = r#"{"metadata":{"name":"p"}}"#;
+        let cases = vec![
+            (
+                RetryPolicy::default(),
+                Request::post("/api/v1/pods").body(Body::empty()),
+                1,
+            ),
+            (RetryPolicy::default(), put(versioned), 1),
+            (RetryPolicy::default().retry_updates(), put(unversioned), 1),
+            (RetryPolicy::default().retry_updates(), put(versioned), 2),
+            (
+                RetryPolicy::default(),
+                Request::get("/api/v1/pods?watch=true&resourceVersion=1").body(Body::empty()),
+                1,
+            ),
+        ];
+        for (policy, req, expected) in cases {
+            let (service, attempts) = flaky(vec![status(StatusCode::SERVICE_UNAVAILABLE)]);
+            RetryLayer::new(policy)
+                .layer(service)
+                .oneshot(req.unwrap())
+                .await
+                .unwrap();
+            assert_eq!(attempts.load(Ordering::SeqCst), expected);
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = RetryPolicy::default();
+        assert_eq!(backoff(&policy, 0), Duration::from_millis(500));
+        assert_eq!(backoff(&policy, 3), Duration::from_secs(4));
+        assert_eq!(backoff(&policy, 10), Duration::from_secs(10));
+        assert_eq!(backoff(&policy, 40), Duration::from_secs(10));
+    }
+}
@@ -8,7 +8,7 @@
 //! The [`Client`] can also be used with [`Discovery`](crate::Discovery) to dynamically
 //! retrieve the resources served by the kubernetes API.
 
-use std::convert::TryFrom;
+use std::{convert::TryFrom, sync::Arc, time::Instant};
 
 use bytes::Bytes;
 use either::{Either, Left, Right};
@@ -30,6 +30,7 @@ use tower::{buffer::Buffer, util::BoxService, BoxError, Layer, Service, ServiceB
 use tower_http::{
     classify::ServerErrorsFailureClass, map_response_body::MapResponseBodyLayer, trace::TraceLayer,
 };
+use tracing::Instrument;
 
 use crate::{api::WatchEvent, error::ErrorResponse, Config, Error, Result};
 
@@ -40,7 +41,15 @@ use body::BodyStreamExt;
 mod config_ext;
 pub use config_ext::ConfigExt;
 pub mod middleware;
+mod proxy;
+mod registry;
+mod socket;
 #[cfg(any(feature = "native-tls", feature = "rustls-tls"))] mod tls;
+#[cfg(any(feature = "native-tls", feature = "rustls-tls"))] mod tls_reload;
+mod trace;
+mod warning;
+pub use registry::ClusterRegistry;
+pub use warning::{LogWarnings, Warning, WarningHandler};
 
 // Binary subprotocol v4. See `Client::connect`.
 #[cfg(feature = "ws")]
@@ -59,6 +68,8 @@ pub struct Client {
     // - `BoxService` for dynamic response future type
     inner: Buffer<BoxService<Request<Body>, Response<Body>, BoxError>, Request<Body>>,
     default_ns: String,
+    warning_handler: Arc<dyn WarningHandler>,
+    warnings_as_errors: bool,
 }
 
 impl Client {
@@ -83,6 +94,8 @@ impl Client {
     /// let config = Config::infer().await?;
     /// let service = ServiceBuilder::new()
     ///     .layer(config.base_uri_layer())
+    ///     .option_layer(config.retry_layer())
+    ///     .option_layer(config.rate_limit_layer())
     ///     .option_layer(config.auth_layer()?)
     ///     .service(hyper::Client::new());
     /// let client = Client::new(service, config.default_namespace);
@@ -105,6 +118,8 @@ impl Client {
         Self {
             inner: Buffer::new(BoxService::new(service), 1024),
             default_ns: default_namespace.into(),
+            warning_handler: Arc::new(LogWarnings),
+            warnings_as_errors: false,
         }
     }
 
@@ -122,31 +137,119 @@ impl Client {
         Self::try_from(Config::infer().await?)
     }
 
+    /// Create a [`Client`] that acts as `user` in `groups` through [user impersonation].
+    ///
+    /// The returned client shares the connection and credentials of this one, and replaces any
+    /// impersonation configured on it. The authenticated user needs the `impersonate` verb on
+    /// the `users` and `groups` resources.
+    ///
+    /// ```no_run
+    /// # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+    /// use k8s_openapi::api::core::v1::Pod;
+    /// use kube::{api::Api, Client};
+    ///
+    /// let client = Client::try_default().await?;
+    /// let sa = client.impersonate("system:serviceaccount:default:reader", &["system:serviceaccounts"])?;
+    /// let pods: Api<Pod> = Api::default_namespaced(sa);
+    /// // fails unless the service account is allowed to list pods
+    /// pods.list(&Default::default()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [user impersonation]: https://kubernetes.io/docs/reference/access-authn-authz/authentication/#user-impersonation
+    pub fn impersonate(&self, user: &str, groups: &[&str]) -> Result<Self> {
+        let impersonation = crate::config::Impersonation::new(user, groups.iter().copied());
+        let service = middleware::ImpersonateLayer::new(&impersonation)?.layer(self.inner.clone());
+        Ok(Self {
+            inner: Self::new(service, "").inner,
+            ..self.clone()
+        })
+    }
+
+    /// Make a new `Client` that sends mutations as dry runs and records them in `layer`.
+    ///
+    /// Every `POST`, `PUT`, `PATCH` and `DELETE` is sent with `dryRun=All`, so the apiserver validates
+    /// and admits it without persisting anything. See [`DryRunLayer`](middleware::DryRunLayer).
+    pub fn dry_run(&self, layer: &middleware::DryRunLayer) -> Self {
+        Self {
+            inner: Self::new(layer.layer(self.inner.clone()), "").inner,
+            ..self.clone()
+        }
+    }
+
+    /// Pass the [`Warning`]s returned by the apiserver to `handler` instead of logging them.
+    ///
+    /// ```no_run
+    /// # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+    /// use kube::{client::Warning, Client};
+    ///
+    /// let client = Client::try_default()
+    ///     .await?
+    ///     .with_warning_handler(|w: &Warning| eprintln!("warning: {}", w));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_warning_handler(mut self, handler: impl WarningHandler + 'static) -> Self {
+        self.warning_handler = Arc::new(handler);
+        self
+    }
+
+    /// Fail successful requests with [`Error::Warnings`] when the apiserver returned warnings.
+    ///
+    /// The warnings are still passed to the warning handler first. Note that the apiserver has
+    /// already processed the request, so a create or update that fails this way was still applied.
+    pub fn warnings_as_errors(mut self) -> Self {
+        self.warnings_as_errors = true;
+        self
+    }
+
     pub(crate) fn default_ns(&self) -> &str {
         &self.default_ns
     }
 
-    async fn send(&self, request: Request<Body>) -> Result<Response<Body>> {
-        let mut svc = self.inner.clone();
-        let res = svc
-            .ready()
-            .await
-            .map_err(Error::Service)?
-            .call(request)
-            .await
-            .map_err(|err| {
-                if err.is::<Error>() {
-                    // Error decorating request
-                    *err.downcast::<Error>().expect("kube::Error")
-                } else if err.is::<hyper::Error>() {
-                    // Error requesting
-                    Error::HyperError(*err.downcast::<hyper::Error>().expect("hyper::Error"))
-                } else {
-                    // Errors from other middlewares
-                    Error::Service(err)
-                }
-            })?;
-        Ok(res)
+    async fn send(&self, mut request: Request<Body>) -> Result<Response<Body>> {
+        // Requests are sent from the task of the `Buffer` worker, create the span in the task of the caller
+        let span = trace::make_span(&request);
+        trace::inject(&span, request.headers_mut());
+        let started = Instant::now();
+        async move {
+            let mut svc = self.inner.clone();
+            let res = svc
+                .ready()
+                .await
+                .map_err(Error::Service)?
+                .call(request)
+                .await
+                .map_err(|err| {
+                    if err.is::<Error>() {
+                        // Error decorating request
+                        *err.downcast::<Error>().expect("kube::Error")
+                    } else if err.is::<hyper::Error>() {
+                        // Error requesting
+                        Error::HyperError(*err.downcast::<hyper::Error>().expect("hyper::Error"))
+                    } else {
+                        // Errors from other middlewares
+                        Error::Service(err)
+                    }
+                })?;
+            trace::on_response(&res, started.elapsed(), &tracing::Span::current());
+            self.handle_warnings(&res)?;
+            Ok(res)
+        }
+        .instrument(span)
+        .await
+    }
+
+    fn handle_warnings(&self, res: &Response<Body>) -> Result<()> {
+        let warnings = Warning::from_headers(res.headers());
+        for warning in &warnings {
+            self.warning_handler.handle_warning(warning);
+        }
+        if self.warnings_as_errors && !warnings.is_empty() && res.status().is_success() {
+            return Err(Error::Warnings(warnings));
+        }
+        Ok(())
     }
 
     /// Make WebSocket connection.
@@ -421,30 +524,135 @@ impl TryFrom<Config> for Client {
 
         let timeout = config.timeout;
         let default_ns = config.default_namespace.clone();
-
-        let client: hyper::Client<_, Body> = {
+        // Exec plugins may issue both a token and a client certificate, so run them only once for both
+        #[cfg_attr(
+            not(any(feature = "native-tls", feature = "rustls-tls")),
+            allow(unused_variables)
+        )]
+        let (auth, exec_identity) = auth::Auth::load(&config.auth_info)?;
+
+        let client = {
             let mut connector = HttpConnector::new();
             connector.enforce_http(false);
+            // Tunnel through the configured proxy before any TLS is layered on top.
+            // This also covers websocket upgrades since they share the same connector.
+            let proxy_url = config.proxy_url.clone().or_else(|| {
+                // The host of unix socket configs is only used for the `Host` header
+                config
+                    .unix_socket
+                    .is_none()
+                    .then(|| proxy::proxy_from_env(&config.cluster_url))
+                    .flatten()
+            });
+            let connector = proxy::ProxyConnector::new(connector, proxy_url.as_ref())?;
+            let connector = socket::SocketConnector::new(connector, config.unix_socket.clone());
+            // Verify the certificate against `tls_server_name` when set
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+            let (cluster_host, server_name) = {
+                if let Some(name) = &config.tls_server_name {
+                    name.parse::<http::uri::Authority>()?;
+                }
+                (
+                    config.cluster_url.host().map(String::from),
+                    config.tls_server_name.clone(),
+                )
+            };
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+            let connector = tls::RewriteHost::new(connector, server_name.as_deref(), cluster_host.as_deref());
 
             // Note that if both `native_tls` and `rustls` is enabled, `native_tls` is used by default.
             // To use `rustls`, disable `native_tls` or create custom client.
             // If tls features are not enabled, http connector will be used.
+            // The TLS connectors are rebuilt whenever a client certificate from an exec plugin expires,
+            // or the files of the certificates change.
+            // Requests are multiplexed over HTTP/2 when the apiserver negotiates it with ALPN, but websocket
+            // upgrades for `exec` and `attach` need HTTP/1.1, so they get separate connections.
             #[cfg(feature = "native-tls")]
-            let connector = hyper_tls::HttpsConnector::from((
-                connector,
-                tokio_native_tls::TlsConnector::from(config.native_tls_connector()?),
-            ));
+            let (http2, http1) = {
+                let accept_invalid = config.accept_invalid_certs;
+                let connectors = tls_reload::TlsReloadConnector::new(
+                    move |identity_pem, root_cert| {
+                        let https = |alpn| -> Result<_> {
+                            let tls = tls::native_tls::native_tls_connector(
+                                identity_pem,
+                                root_cert,
+                                accept_invalid,
+                                alpn,
+                            )?;
+                            let https = tls::native_tls::AlpnHttpsConnector::from((
+                                connector.clone(),
+                                tokio_native_tls::TlsConnector::from(tls),
+                            ));
+                            Ok(tls::RewriteHost::new(
+                                https,
+                                cluster_host.as_deref(),
+                                server_name.as_deref(),
+                            ))
+                        };
+                        Ok((https(tls::ALPN_HTTP2)?, https(tls::ALPN_HTTP1)?))
+                    },
+                    config.identity_pem.as_ref(),
+                    config.root_cert.as_ref(),
+                    exec_identity,
+                    config.tls_files.as_ref(),
+                )?;
+                (
+                    connectors.select(|(http2, _)| http2.clone()),
+                    connectors.select(|(_, http1)| http1.clone()),
+                )
+            };
             #[cfg(all(not(feature = "native-tls"), feature = "rustls-tls"))]
-            let connector = hyper_rustls::HttpsConnector::from((
-                connector,
-                std::sync::Arc::new(config.rustls_client_config()?),
-            ));
-
-            let mut connector = TimeoutConnector::new(connector);
-            connector.set_connect_timeout(timeout);
-            connector.set_read_timeout(timeout);
-
-            hyper::Client::builder().build(connector)
+            let (http2, http1) = {
+                tls::rustls_tls::check_server_name(&config.cluster_url, config.tls_server_name.as_deref())?;
+                let accept_invalid = config.accept_invalid_certs;
+                let connectors = tls_reload::TlsReloadConnector::new(
+                    move |identity_pem, root_cert| {
+                        let https = |alpn| -> Result<_> {
+                            let tls = tls::rustls_tls::rustls_client_config(
+                                identity_pem,
+                                root_cert,
+                                accept_invalid,
+                                alpn,
+                            )?;
+                            let https = hyper_rustls::HttpsConnector::from((
+                                connector.clone(),
+                                std::sync::Arc::new(tls),
+                            ));
+                            Ok(tls::RewriteHost::new(
+                                https,
+                                cluster_host.as_deref(),
+                                server_name.as_deref(),
+                            ))
+                        };
+                        Ok((https(tls::ALPN_HTTP2)?, https(tls::ALPN_HTTP1)?))
+                    },
+                    config.identity_pem.as_ref(),
+                    config.root_cert.as_ref(),
+                    exec_identity,
+                    config.tls_files.as_ref(),
+                )?;
+                (
+                    connectors.select(|(http2, _)| http2.clone()),
+                    connectors.select(|(_, http1)| http1.clone()),
+                )
+            };
+            #[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+            let (http2, http1) = (connector.clone(), connector);
+
+            let build = |connector| {
+                let mut connector = TimeoutConnector::new(connector);
+                connector.set_connect_timeout(timeout);
+                connector.set_read_timeout(timeout);
+                hyper::Client::builder().build::<_, Body>(connector)
+            };
+            let (http2, http1) = (build(http2), build(http1));
+            tower::service_fn(move |req: Request<Body>| {
+                if req.headers().contains_key(http::header::UPGRADE) {
+                    http1.request(req)
+                } else {
+                    http2.request(req)
+                }
+            })
         };
 
         let stack = ServiceBuilder::new().layer(config.base_uri_layer()).into_inner();
@@ -456,32 +664,19 @@ impl TryFrom<Config> for Client {
 
         let service = ServiceBuilder::new()
             .layer(stack)
-            .option_layer(config.auth_layer()?)
+            .option_layer(config.retry_layer())
+            .option_layer(config.rate_limit_layer())
+            .option_layer(config_ext::auth_layer_for(auth))
+            .option_layer(config.impersonate_layer()?)
             .layer(
-                // Attribute names follow [Semantic Conventions].
-                // [Semantic Conventions]: https://github.com/open-telemetry/opentelemetry-specification/blob/main/specification/trace/semantic_conventions/http.md
                 TraceLayer::new_for_http()
-                    .make_span_with(|req: &Request<hyper::Body>| {
-                        tracing::debug_span!(
-                            "HTTP",
-                             http.method = %req.method(),
-                             http.url = %req.uri(),
-                             http.status_code = tracing::field::Empty,
-                             otel.name = req.extensions().get::<&'static str>().unwrap_or(&"HTTP"),
-                             otel.kind = "client",
-                             otel.status_code = tracing::field::Empty,
-                        )
-                    })
+                    // The request span is created by `Client::send` and entered by the `Buffer` worker
+                    .make_span_with(|_req: &Request<hyper::Body>| Span::current())
                     .on_request(|_req: &Request<hyper::Body>, _span: &Span| {
                         tracing::debug!("requesting");
                     })
-                    .on_response(|res: &Response<hyper::Body>, _latency: Duration, span: &Span| {
-                        let status = res.status();
-                        span.record("http.status_code", &status.as_u16());
-                        if status.is_client_error() || status.is_server_error() {
-                            span.record("otel.status_code", &"ERROR");
-                        }
-                    })
+                    // Recorded by `Client::send`
+                    .on_response(())
                     // Explicitly disable `on_body_chunk`. The default does nothing.
                     .on_body_chunk(())
                     .on_eos(|_: Option<&HeaderMap>, _duration: Duration, _span: &Span| {
@@ -493,10 +688,10 @@ impl TryFrom<Config> for Client {
                         // - Polling `Body` errored
                         // - the response was classified as failure (5xx)
                         // - End of stream was classified as failure
-                        span.record("otel.status_code", &"ERROR");
+                        span.record("otel.status_code", "ERROR");
                         match ec {
                             ServerErrorsFailureClass::StatusCode(status) => {
-                                span.record("http.status_code", &status.as_u16());
+                                span.record("http.status_code", status.as_u16());
                                 tracing::error!("failed with status {}", status)
                             }
                             ServerErrorsFailureClass::Error(err) => {
@@ -0,0 +1,405 @@
+//! A registry of clients for many clusters
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use k8s_openapi::api::core::v1::Secret;
+
+use crate::{
+    config::{hash_file, KubeConfigOptions, Kubeconfig},
+    error::ConfigError,
+    Client, Config, Error, Result,
+};
+
+/// Builds and keeps one [`Client`] per cluster, keyed by the name of the cluster.
+///
+/// Clusters are added from kubeconfig contexts, a directory of kubeconfig files, or `Secret`s
+/// holding kubeconfigs, and are only connected to when their client is first requested with
+/// [`ClusterRegistry::client`]. Inserting a cluster again with different credentials replaces
+/// its client, so registries can be refreshed by loading their sources again. This includes changes to
+/// the contents of the certificate, key and token files that the kubeconfig refers to.
+///
+/// Clones share the same clusters.
+///
+/// ```no_run
+/// # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+/// use k8s_openapi::api::core::v1::Pod;
+/// use kube::{client::ClusterRegistry, config::Kubeconfig, Api};
+///
+/// let registry = ClusterRegistry::new();
+/// registry.insert_contexts(&Kubeconfig::read()?)?;
+/// for cluster in registry.clusters() {
+///     let pods: Api<Pod> = Api::all(registry.client(&cluster).await?);
+///     println!("{} has {} pods", cluster, pods.list(&Default::default()).await?.items.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct ClusterRegistry {
+    clusters: Arc<Mutex<BTreeMap<String, Entry>>>,
+}
+
+struct Entry {
+    kubeconfig: Kubeconfig,
+    options: KubeConfigOptions,
+    // The context, cluster and user of the kubeconfig serialized with the hashes of their files,
+    // to tell whether credentials changed
+    fingerprint: String,
+    // The directory the kubeconfig was loaded from by `load_dir`
+    dir: Option<PathBuf>,
+    client: Option<Client>,
+}
+
+impl ClusterRegistry {
+    /// Create a registry without clusters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a cluster, or replace the cluster with the same name.
+    ///
+    /// `options` select the context of `kubeconfig` to connect with.
+    /// Returns whether the cluster changed. The client of a changed cluster is rebuilt
+    /// the next time it is requested, while an unchanged cluster keeps its client.
+    pub fn insert(&self, name: &str, kubeconfig: Kubeconfig, options: KubeConfigOptions) -> Result<bool> {
+        self.insert_entry(name, kubeconfig, options, None)
+    }
+
+    /// Add a cluster for every context of `kubeconfig`, keyed by the name of the context's cluster.
+    ///
+    /// When several contexts use the same cluster, the first one is used.
+    pub fn insert_contexts(&self, kubeconfig: &Kubeconfig) -> Result<()> {
+        let mut seen = Vec::new();
+        for named in &kubeconfig.contexts {
+            let cluster = &named.context.cluster;
+            if seen.contains(&cluster) {
+                continue;
+            }
+            seen.push(cluster);
+            let options = KubeConfigOptions {
+                context: Some(named.name.clone()),
+                ..KubeConfigOptions::default()
+            };
+            self.insert(cluster, kubeconfig.clone(), options)?;
+        }
+        Ok(())
+    }
+
+    /// Add a cluster for the kubeconfig stored under `key` in a `Secret`, using its current context.
+    ///
+    /// Cluster API stores kubeconfigs of workload clusters under the `value` key of `<cluster>-kubeconfig` secrets.
+    pub fn insert_secret(&self, name: &str, secret: &Secret, key: &str) -> Result<bool> {
+        let data = secret
+            .data
+            .get(key)
+            .ok_or_else(|| ConfigError::KubeconfigSecret {
+                name: secret.metadata.name.clone().unwrap_or_default(),
+                key: key.to_owned(),
+            })?;
+        let kubeconfig = serde_yaml::from_slice(&data.0).map_err(ConfigError::ParseYaml)?;
+        self.insert(name, kubeconfig, KubeConfigOptions::default())
+    }
+
+    /// Add a cluster for every kubeconfig file in a directory, keyed by the file name, using its current context.
+    ///
+    /// Hidden files are skipped, which includes the timestamped directories of mounted `Secret`s and `ConfigMap`s.
+    /// Loading the same directory again refreshes the clusters whose files changed,
+    /// and removes the clusters whose files were deleted.
+    ///
+    /// Files that fail to load are skipped and returned with their errors, so that one bad file does not
+    /// prevent loading the others. Clusters loaded from such a file before keep their previous kubeconfig.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory can not be read.
+    pub fn load_dir<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<(PathBuf, Error)>> {
+        let dir = dir.as_ref();
+        let read_dir = |dir: &Path| -> std::io::Result<Vec<(String, PathBuf)>> {
+            let mut files = Vec::new();
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                // Follows symlinks, like the ones of mounted secrets
+                if !name.starts_with('.') && entry.path().is_file() {
+                    files.push((name, entry.path()));
+                }
+            }
+            Ok(files)
+        };
+        let files = read_dir(dir).map_err(|source| ConfigError::ReadFile {
+            path: dir.into(),
+            source,
+        })?;
+
+        let mut failures = Vec::new();
+        for (name, path) in &files {
+            let loaded = Kubeconfig::read_from(path).and_then(|kubeconfig| {
+                self.insert_entry(name, kubeconfig, KubeConfigOptions::default(), Some(dir))
+            });
+            if let Err(err) = loaded {
+                tracing::warn!("failed to load cluster {} from {:?}: {}", name, path, err);
+                failures.push((path.clone(), err));
+            }
+        }
+        self.lock()
+            .retain(|name, entry| entry.dir.as_deref() != Some(dir) || files.iter().any(|(n, _)| n == name));
+        Ok(failures)
+    }
+
+    /// Remove a cluster, returning whether it was in the registry.
+    pub fn remove(&self, name: &str) -> bool {
+        self.lock().remove(name).is_some()
+    }
+
+    /// The names of all clusters, in order.
+    pub fn clusters(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    /// The client of a cluster, connecting to the cluster if it has no client yet.
+    pub async fn client(&self, name: &str) -> Result<Client> {
+        let (kubeconfig, options, fingerprint) = {
+            let clusters = self.lock();
+            let entry = clusters.get(name).ok_or_else(|| ConfigError::UnknownCluster {
+                cluster_name: name.to_owned(),
+            })?;
+            if let Some(client) = &entry.client {
+                return Ok(client.clone());
+            }
+            (
+                entry.kubeconfig.clone(),
+                entry.options.clone(),
+                entry.fingerprint.clone(),
+            )
+        };
+
+        tracing::debug!("connecting to cluster {}", name);
+        let config = Config::from_custom_kubeconfig(kubeconfig, &options).await?;
+        let client = Client::try_from(config)?;
+        // Only keep the client if the cluster did not change while connecting
+        if let Some(entry) = self.lock().get_mut(name) {
+            if entry.fingerprint == fingerprint && entry.client.is_none() {
+                entry.client = Some(client.clone());
+            }
+        }
+        Ok(client)
+    }
+
+    fn insert_entry(
+        &self,
+        name: &str,
+        kubeconfig: Kubeconfig,
+        options: KubeConfigOptions,
+        dir: Option<&Path>,
+    ) -> Result<bool> {
+        let fingerprint = fingerprint(&kubeconfig, &options)?;
+        let mut clusters = self.lock();
+        if let Some(entry) = clusters.get(name) {
+            if entry.fingerprint == fingerprint && entry.options == options {
+                return Ok(false);
+            }
+            tracing::debug!("credentials of cluster {} changed", name);
+        }
+        clusters.insert(name.to_owned(), Entry {
+            kubeconfig,
+            options,
+            fingerprint,
+            dir: dir.map(Path::to_path_buf),
+            client: None,
+        });
+        Ok(true)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Entry>> {
+        self.clusters.lock().expect("cluster registry lock poisoned")
+    }
+}
+
+// Serializes the context, cluster and user that `options` select from `kubeconfig`,
+// so that changes to other entries of a shared kubeconfig do not replace the client.
+// The files they refer to are hashed as well, so that rotated credentials replace the client.
+fn fingerprint(kubeconfig: &Kubeconfig, options: &KubeConfigOptions) -> Result<String> {
+    let context = options
+        .context
+        .as_ref()
+        .or(kubeconfig.current_context.as_ref())
+        .and_then(|name| kubeconfig.contexts.iter().find(|named| &named.name == name));
+    let cluster = options
+        .cluster
+        .as_ref()
+        .or_else(|| context.map(|named| &named.context.cluster))
+        .and_then(|name| kubeconfig.clusters.iter().find(|named| &named.name == name));
+    let user = options
+        .user
+        .as_ref()
+        .or_else(|| context.map(|named| &named.context.user))
+        .and_then(|name| kubeconfig.auth_infos.iter().find(|named| &named.name == name));
+    let files = [
+        cluster.and_then(|named| named.cluster.certificate_authority.as_ref()),
+        user.and_then(|named| named.auth_info.client_certificate.as_ref()),
+        user.and_then(|named| named.auth_info.client_key.as_ref()),
+        user.and_then(|named| named.auth_info.token_file.as_ref()),
+    ]
+    .iter()
+    .map(|path| path.and_then(hash_file))
+    .collect::<Vec<_>>();
+    Ok(serde_json::to_string(&(context, cluster, user, files)).map_err(ConfigError::SerializeKubeconfig)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::ByteString;
+
+    use super::*;
+
+    fn kubeconfig(server: &str) -> String {
+        format!(
+            "apiVersion: v1
+kind: Config
+clusters:
+- name: c
+  cluster:
+    server: {}
+contexts:
+- name: c
+  context:
+    cluster: c
+    user: u
+users:
+- name: u
+  user:
+    token: t
+current-context: c
+",
+            server
+        )
+    }
+
+    #[tokio::test]
+    async fn caches_clients_until_credentials_change() {
+        let registry = ClusterRegistry::new();
+        let secret = |server: &str| Secret {
+            data: vec![("value".to_owned(), ByteString(kubeconfig(server).into_bytes()))]
+                .into_iter()
+                .collect(),
+            ..Secret::default()
+        };
+        assert!(registry
+            .insert_secret("a", &secret("https://a:6443"), "value")
+            .unwrap());
+        assert!(registry
+            .insert_secret("a", &secret("https://a:6443"), "missing")
+            .is_err());
+        assert!(registry.client("b").await.is_err());
+
+        registry.client("a").await.unwrap();
+        assert!(registry.lock()["a"].client.is_some());
+        assert!(!registry
+            .insert_secret("a", &secret("https://a:6443"), "value")
+            .unwrap());
+        assert!(registry.lock()["a"].client.is_some());
+        assert!(registry
+            .insert_secret("a", &secret("https://a:8443"), "value")
+            .unwrap());
+        assert!(registry.lock()["a"].client.is_none());
+    }
+
+    #[test]
+    fn loads_clusters_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), kubeconfig("https://a:6443")).unwrap();
+        fs::write(dir.path().join("b"), kubeconfig("https://b:6443")).unwrap();
+        fs::create_dir(dir.path().join("..data")).unwrap();
+        let registry = ClusterRegistry::new();
+        registry
+            .insert("other", Kubeconfig::default(), KubeConfigOptions::default())
+            .unwrap();
+
+        assert!(registry.load_dir(dir.path()).unwrap().is_empty());
+        assert_eq!(registry.clusters(), vec!["a", "b", "other"]);
+        fs::remove_file(dir.path().join("b")).unwrap();
+        assert!(registry.load_dir(dir.path()).unwrap().is_empty());
+        assert_eq!(registry.clusters(), vec!["a", "other"]);
+
+        // Bad files are reported without affecting the other clusters
+        fs::write(dir.path().join("a"), "not a kubeconfig").unwrap();
+        fs::write(dir.path().join("c"), kubeconfig("https://c:6443")).unwrap();
+        let failures = registry.load_dir(dir.path()).unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, dir.path().join("a"));
+        assert_eq!(registry.clusters(), vec!["a", "c", "other"]);
+        assert_eq!(
+            registry.lock()["a"].kubeconfig.clusters[0].cluster.server,
+            "https://a:6443"
+        );
+    }
+
+    #[test]
+    fn referenced_files_are_part_of_the_fingerprint() {
+        let dir = tempfile::tempdir().unwrap();
+        let token = dir.path().join("token");
+        fs::write(&token, "token-1").unwrap();
+        let kubeconfig =
+            kubeconfig("https://a:6443").replace("token: t", &format!("tokenFile: {}", token.display()));
+        let kubeconfig: Kubeconfig = serde_yaml::from_str(&kubeconfig).unwrap();
+        let registry = ClusterRegistry::new();
+        let insert = || {
+            registry
+                .insert("a", kubeconfig.clone(), KubeConfigOptions::default())
+                .unwrap()
+        };
+
+        assert!(insert());
+        assert!(!insert());
+        // Same size, different contents
+        fs::write(&token, "token-2").unwrap();
+        assert!(insert());
+        assert!(!insert());
+    }
+
+    #[tokio::test]
+    async fn contexts_only_change_with_their_entries() {
+        let config = |b_server: &str| -> Kubeconfig {
+            serde_yaml::from_str(&format!(
+                "apiVersion: v1
+kind: Config
+clusters:
+- name: a
+  cluster:
+    server: https://a:6443
+- name: b
+  cluster:
+    server: {}
+contexts:
+- name: a
+  context:
+    cluster: a
+    user: u
+- name: b
+  context:
+    cluster: b
+    user: u
+users:
+- name: u
+  user:
+    token: t
+",
+                b_server
+            ))
+            .unwrap()
+        };
+        let registry = ClusterRegistry::new();
+        registry.insert_contexts(&config("https://b:6443")).unwrap();
+        registry.client("a").await.unwrap();
+        registry.client("b").await.unwrap();
+
+        registry.insert_contexts(&config("https://b:8443")).unwrap();
+        assert!(registry.lock()["a"].client.is_some());
+        assert!(registry.lock()["b"].client.is_none());
+    }
+}
@@ -0,0 +1,182 @@
+//! Connector to the apiserver over TCP or a unix domain socket
+use std::{
+    future::Future,
+    io,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::Uri;
+use hyper::client::connect::{Connected, Connection};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+#[cfg(unix)] use tokio::net::UnixStream;
+use tower::{BoxError, Service};
+
+use super::proxy::ProxyStream;
+
+/// A connector that connects to [`Config::unix_socket`](crate::Config::unix_socket) when it is set,
+/// and otherwise uses the inner TCP connector, which may tunnel through a proxy.
+///
+/// The host of the request uri is ignored for unix sockets, so TLS connectors layered on top
+/// pass the `http://localhost` cluster url of unix socket configs through unencrypted.
+#[derive(Clone)]
+pub(crate) struct SocketConnector<C> {
+    tcp: C,
+    unix_socket: Option<Arc<PathBuf>>,
+}
+
+impl<C> SocketConnector<C> {
+    pub(crate) fn new(tcp: C, unix_socket: Option<PathBuf>) -> Self {
+        Self {
+            tcp,
+            unix_socket: unix_socket.map(Arc::new),
+        }
+    }
+}
+
+impl<C> Service<Uri> for SocketConnector<C>
+where
+    C: Service<Uri, Response = ProxyStream>,
+    C::Error: Into<BoxError>,
+    C::Future: Send + 'static,
+{
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+    type Response = Stream;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.unix_socket.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        self.tcp.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        match self.unix_socket.clone() {
+            #[cfg(unix)]
+            Some(path) => Box::pin(async move {
+                tracing::trace!("connecting to unix socket {}", path.display());
+                Ok(Stream::Unix(UnixStream::connect(path.as_path()).await?))
+            }),
+            #[cfg(not(unix))]
+            Some(_) => Box::pin(async { Err("unix sockets are not supported on this platform".into()) }),
+            None => {
+                let connecting = self.tcp.call(dst);
+                Box::pin(async move { Ok(Stream::Tcp(connecting.await.map_err(Into::into)?)) })
+            }
+        }
+    }
+}
+
+/// Stream of [`SocketConnector`].
+pub(crate) enum Stream {
+    Tcp(ProxyStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        match self {
+            Self::Tcp(stream) => stream.connected(),
+            #[cfg(unix)]
+            Self::Unix(_) => Connected::new(),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::convert::TryFrom;
+
+    use http::{Request, Response};
+    use hyper::{server::conn::Http, service::service_fn, Body};
+    use k8s_openapi::api::core::v1::Pod;
+    use tokio::net::UnixListener;
+
+    use crate::{Api, Client, Config};
+
+    #[tokio::test]
+    async fn requests_over_unix_socket() {
+        let dir = std::env::temp_dir().join(format!("kube-socket-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("apiserver.sock");
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|req: Request<Body>| async move {
+                assert_eq!(req.uri().path(), "/api/v1/namespaces/default/pods/p");
+                assert_eq!(req.headers()[http::header::HOST], "localhost");
+                let pod =
+                    serde_json::json!({ "apiVersion": "v1", "kind": "Pod", "metadata": { "name": "p" } });
+                Ok::<_, hyper::Error>(Response::new(Body::from(pod.to_string())))
+            });
+            Http::new().serve_connection(stream, service).await.unwrap();
+        });
+
+        let client = Client::try_from(Config::from_unix_socket(&path)).unwrap();
+        let pods: Api<Pod> = Api::default_namespaced(client);
+        let pod = pods.get("p").await.unwrap();
+        assert_eq!(pod.metadata.name.as_deref(), Some("p"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
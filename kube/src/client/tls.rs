@@ -1,18 +1,88 @@
+/// ALPN protocols to multiplex requests over HTTP/2 when the apiserver supports it
+pub const ALPN_HTTP2: &[&str] = &["h2", "http/1.1"];
+/// ALPN protocols for connections that need HTTP/1.1, like websocket upgrades
+pub const ALPN_HTTP1: &[&str] = &["http/1.1"];
+
+/// Connector replacing the host `from` with `to` in the uris it connects to.
+///
+/// TLS connectors verify the certificate of the host they connect to. To verify against
+/// `tls-server-name` instead, the connector above TLS replaces the cluster host with the
+/// server name, and the one below TLS replaces it back to connect to the cluster host.
+#[derive(Clone)]
+pub struct RewriteHost<C> {
+    inner: C,
+    rewrite: Option<(std::sync::Arc<str>, std::sync::Arc<str>)>,
+}
+
+impl<C> RewriteHost<C> {
+    /// Pass uris to `inner` unchanged, unless both `from` and `to` are set.
+    pub fn new(inner: C, from: Option<&str>, to: Option<&str>) -> Self {
+        Self {
+            inner,
+            rewrite: from.zip(to).map(|(from, to)| (from.into(), to.into())),
+        }
+    }
+}
+
+impl<C: tower::Service<http::Uri>> tower::Service<http::Uri> for RewriteHost<C> {
+    type Error = C::Error;
+    type Future = C::Future;
+    type Response = C::Response;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, dst: http::Uri) -> Self::Future {
+        let dst = match &self.rewrite {
+            Some((from, to)) if dst.host() == Some(from) => with_host(&dst, to).unwrap_or(dst),
+            _ => dst,
+        };
+        self.inner.call(dst)
+    }
+}
+
+fn with_host(uri: &http::Uri, host: &str) -> Option<http::Uri> {
+    let authority = match uri.port_u16() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_owned(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.authority = Some(authority.parse().ok()?);
+    http::Uri::from_parts(parts).ok()
+}
+
 #[cfg(feature = "native-tls")]
 pub mod native_tls {
+    use std::{
+        future::Future,
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use http::Uri;
+    use hyper::client::connect::{Connected, Connection};
+    use hyper_tls::{HttpsConnector, MaybeHttpsStream};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
     use tokio_native_tls::native_tls::{Certificate, Identity, TlsConnector};
+    use tower::{BoxError, Service};
 
     use crate::{Error, Result};
 
     const IDENTITY_PASSWORD: &str = " ";
 
-    /// Create `native_tls::TlsConnector`.
+    /// Create `native_tls::TlsConnector`, offering the `alpn` protocols if any.
     pub fn native_tls_connector(
         identity_pem: Option<&Vec<u8>>,
         root_cert: Option<&Vec<Vec<u8>>>,
         accept_invalid: bool,
+        alpn: &[&str],
     ) -> Result<TlsConnector> {
         let mut builder = TlsConnector::builder();
+        if !alpn.is_empty() {
+            builder.request_alpns(alpn);
+        }
         if let Some(pem) = identity_pem {
             let identity = pkcs12_from_pem(pem, IDENTITY_PASSWORD)?;
             builder.identity(
@@ -46,6 +116,92 @@ pub mod native_tls {
         let der = p12.to_der()?;
         Ok(der)
     }
+
+    /// `hyper_tls::HttpsConnector` that tells hyper when HTTP/2 was negotiated with ALPN.
+    #[derive(Clone)]
+    pub struct AlpnHttpsConnector<T>(HttpsConnector<T>);
+
+    impl<T> From<(T, tokio_native_tls::TlsConnector)> for AlpnHttpsConnector<T> {
+        fn from(args: (T, tokio_native_tls::TlsConnector)) -> Self {
+            Self(HttpsConnector::from(args))
+        }
+    }
+
+    impl<T> Service<Uri> for AlpnHttpsConnector<T>
+    where
+        T: Service<Uri>,
+        T::Response: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        T::Future: Send + 'static,
+        T::Error: Into<BoxError>,
+    {
+        type Error = BoxError;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+        type Response = AlpnStream<T::Response>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.0.poll_ready(cx)
+        }
+
+        fn call(&mut self, dst: Uri) -> Self::Future {
+            let connecting = self.0.call(dst);
+            Box::pin(async move { Ok(AlpnStream(connecting.await?)) })
+        }
+    }
+
+    /// Stream of [`AlpnHttpsConnector`].
+    pub struct AlpnStream<T>(MaybeHttpsStream<T>);
+
+    impl<T: AsyncRead + AsyncWrite + Connection + Unpin> Connection for AlpnStream<T> {
+        fn connected(&self) -> Connected {
+            match &self.0 {
+                MaybeHttpsStream::Http(stream) => stream.connected(),
+                MaybeHttpsStream::Https(stream) => {
+                    let tls = stream.get_ref();
+                    let connected = tls.get_ref().get_ref().connected();
+                    match tls.negotiated_alpn() {
+                        Ok(Some(protocol)) if protocol == b"h2" => connected.negotiated_h2(),
+                        _ => connected,
+                    }
+                }
+            }
+        }
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for AlpnStream<T> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_read(cx, buf)
+        }
+    }
+
+    impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for AlpnStream<T> {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.0).poll_write(cx, buf)
+        }
+
+        fn poll_write_vectored(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[io::IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            self.0.is_write_vectored()
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_shutdown(cx)
+        }
+    }
 }
 
 #[cfg(feature = "rustls-tls")]
@@ -57,16 +213,18 @@ pub mod rustls_tls {
 
     use crate::{Error, Result};
 
-    /// Create `rustls::ClientConfig`.
+    /// Create `rustls::ClientConfig`, offering the `alpn` protocols if any.
     pub fn rustls_client_config(
         identity_pem: Option<&Vec<u8>>,
         root_cert: Option<&Vec<Vec<u8>>>,
         accept_invalid: bool,
+        alpn: &[&str],
     ) -> Result<ClientConfig> {
         use std::io::Cursor;
 
         // Based on code from `reqwest`
         let mut client_config = ClientConfig::new();
+        client_config.alpn_protocols = alpn.iter().map(|protocol| protocol.as_bytes().to_vec()).collect();
         if let Some(buf) = identity_pem {
             let (key, certs) = {
                 let mut pem = Cursor::new(buf);
@@ -143,6 +301,28 @@ pub mod rustls_tls {
         Ok(client_config)
     }
 
+    /// Check that the certificate of `cluster_url` can be verified.
+    ///
+    /// webpki only verifies certificates against dns names, so clusters addressed by an ip address
+    /// need a `tls_server_name` with a dns name from the certificate of the apiserver.
+    pub fn check_server_name(cluster_url: &http::Uri, tls_server_name: Option<&str>) -> Result<()> {
+        if cluster_url.scheme_str() != Some("https") {
+            return Ok(());
+        }
+        let name = match tls_server_name.or_else(|| cluster_url.host()) {
+            Some(name) => name.trim_start_matches('[').trim_end_matches(']'),
+            None => return Ok(()),
+        };
+        DNSNameRef::try_from_ascii_str(name).map(|_| ()).map_err(|_| {
+            Error::SslError(format!(
+                "rustls can not verify the certificate of {}, which is not a dns name: \
+                 set tls-server-name in the kubeconfig (Config::tls_server_name) to a dns name \
+                 in the certificate of the apiserver, like kubernetes.default.svc",
+                name
+            ))
+        })
+    }
+
     struct NoCertificateVerification {}
 
     impl ServerCertVerifier for NoCertificateVerification {
@@ -157,3 +337,59 @@ pub mod rustls_tls {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::RewriteHost;
+
+    #[tokio::test]
+    async fn rewrites_host_for_tls_and_back() {
+        let connect = tower::service_fn(|dst: http::Uri| async move { Ok::<_, tower::BoxError>(dst) });
+        // The TCP connector receives the cluster host again
+        let tcp = RewriteHost::new(connect, Some("kubernetes.internal"), Some("10.0.0.1"));
+        // The TLS connector would receive the server name
+        let tls = tower::service_fn(move |dst: http::Uri| {
+            assert_eq!(dst, "https://kubernetes.internal:6443/");
+            tcp.clone().oneshot(dst)
+        });
+        let outer = RewriteHost::new(tls, Some("10.0.0.1"), Some("kubernetes.internal"));
+        let dst = outer
+            .oneshot("https://10.0.0.1:6443".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(dst, "https://10.0.0.1:6443/");
+
+        let unchanged = RewriteHost::new(connect, None, Some("kubernetes.internal"));
+        let dst = unchanged
+            .oneshot("https://10.0.0.1".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(dst, "https://10.0.0.1/");
+    }
+
+    #[cfg(all(not(feature = "native-tls"), feature = "rustls-tls"))]
+    #[tokio::test]
+    async fn rustls_requires_server_name_for_ip_servers() {
+        use std::convert::TryFrom;
+
+        use crate::{Client, Config, Error};
+
+        for server in &["https://10.0.0.1:6443", "https://[fd00::1]:6443"] {
+            let config = Config::new(server.parse().unwrap());
+            match Client::try_from(config.clone()) {
+                Err(Error::SslError(msg)) => assert!(msg.contains("tls-server-name"), "{}", msg),
+                res => panic!("expected an ssl error for {}, got {:?}", server, res.map(|_| ())),
+            }
+
+            let config = Config {
+                tls_server_name: Some("kubernetes.default.svc".into()),
+                ..config
+            };
+            assert!(Client::try_from(config).is_ok());
+        }
+        let config = Config::new("https://kubernetes.default.svc".parse().unwrap());
+        assert!(Client::try_from(config).is_ok());
+    }
+}
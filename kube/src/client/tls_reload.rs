@@ -0,0 +1,336 @@
+//! Connector rebuilt when its client certificate or certificate authority changes.
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http::Uri;
+use tokio::{sync::Mutex, time::Instant};
+use tower::{BoxError, Service, ServiceExt};
+
+use super::auth::ExecIdentity;
+use crate::{config::TlsFiles, error::ConfigError, Result};
+
+// Hashes of the contents of `TlsFiles`
+type FileStamps = Vec<Option<u64>>;
+type Reloaded = (FileStamps, Result<Option<Vec<u8>>>, Result<Option<Vec<Vec<u8>>>>);
+type BuildConnector<C> = Arc<dyn Fn(Option<&Vec<u8>>, Option<&Vec<Vec<u8>>>) -> Result<C> + Send + Sync>;
+
+// Minimum time between checks of `TlsFiles`, which reads and hashes the files
+const FILES_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// TLS connector that is rebuilt before the client certificate from an exec plugin expires,
+/// and when the files of the certificates change.
+///
+/// Connections that are already established keep using the previous certificates.
+/// The built connectors `C` can be a group of connectors, which `D` is selected from.
+#[derive(Clone)]
+pub(crate) struct TlsReloadConnector<C, D = C> {
+    state: Arc<Mutex<State<C>>>,
+    build: BuildConnector<C>,
+    select: fn(&C) -> D,
+}
+
+struct State<C> {
+    connector: C,
+    identity_pem: Option<Vec<u8>>,
+    root_cert: Option<Vec<Vec<u8>>>,
+    exec_identity: Option<ExecIdentity>,
+    files: Option<(TlsFiles, FileStamps)>,
+    files_checked: Instant,
+}
+
+impl<C: Clone> TlsReloadConnector<C> {
+    /// Build the connector with `build`, given the client certificate and key in PEM and the root certificates.
+    ///
+    /// A certificate configured in `identity_pem` takes precedence over the one from the exec plugin.
+    pub(crate) fn new<F>(
+        build: F,
+        identity_pem: Option<&Vec<u8>>,
+        root_cert: Option<&Vec<Vec<u8>>>,
+        exec_identity: Option<ExecIdentity>,
+        files: Option<&TlsFiles>,
+    ) -> Result<Self>
+    where
+        F: Fn(Option<&Vec<u8>>, Option<&Vec<Vec<u8>>>) -> Result<C> + Send + Sync + 'static,
+    {
+        let exec_identity = exec_identity.filter(|_| identity_pem.is_none());
+        let identity_pem = identity_pem.or_else(|| exec_identity.as_ref().map(|id| &id.pem));
+        let connector = build(identity_pem, root_cert)?;
+        let state = State {
+            connector,
+            identity_pem: identity_pem.cloned(),
+            root_cert: root_cert.cloned(),
+            exec_identity,
+            files: files.map(|files| (files.clone(), files.stamps())),
+            files_checked: Instant::now(),
+        };
+        Ok(Self {
+            state: Arc::new(Mutex::new(state)),
+            build: Arc::new(build),
+            select: C::clone,
+        })
+    }
+}
+
+impl<C, D> TlsReloadConnector<C, D> {
+    /// Connect with the connector picked by `select`, sharing the certificates with `self`.
+    pub(crate) fn select<E>(&self, select: fn(&C) -> E) -> TlsReloadConnector<C, E> {
+        TlsReloadConnector {
+            state: self.state.clone(),
+            build: self.build.clone(),
+            select,
+        }
+    }
+}
+
+impl<C> State<C> {
+    // Rebuild the connector if the exec identity is expiring or the files changed
+    async fn reload(&mut self, build: &BuildConnector<C>) -> Result<()> {
+        let mut changed = false;
+        if let Some(current) = self.exec_identity.clone().filter(|id| id.is_expiring()) {
+            tracing::debug!("refreshing client certificate from exec plugin");
+            // The plugin is a blocking command, which must not stall the runtime
+            let refreshed = tokio::task::spawn_blocking(move || current.refresh())
+                .await
+                .map_err(|err| ConfigError::AuthExec(format!("exec plugin task failed: {}", err)))??;
+            self.identity_pem = Some(refreshed.pem.clone());
+            self.exec_identity = Some(refreshed);
+            changed = true;
+        }
+        let check_files = self.files_checked.elapsed() >= FILES_CHECK_INTERVAL;
+        if let Some((files, stamps)) = self.files.as_mut().filter(|_| check_files) {
+            self.files_checked = Instant::now();
+            // Reading files blocks, which must not stall the runtime
+            let (files, previous) = (files.clone(), stamps.clone());
+            let reloaded = tokio::task::spawn_blocking(move || -> Option<Reloaded> {
+                let current = files.stamps();
+                (current != previous).then(|| {
+                    let identity_pem = files.identity_pem().transpose();
+                    (current, identity_pem, files.root_cert().transpose())
+                })
+            })
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("failed to check TLS certificate files: {}", err);
+                None
+            });
+            if let Some((current, identity_pem, root_cert)) = reloaded {
+                // Files may be read while they are being rotated, so keep the previous certificates
+                // and try again at the next check when they can't be loaded.
+                match (identity_pem, root_cert) {
+                    (Ok(identity_pem), Ok(root_cert)) => {
+                        tracing::debug!("reloading changed TLS certificate files");
+                        if identity_pem.is_some() {
+                            self.identity_pem = identity_pem;
+                        }
+                        if root_cert.is_some() {
+                            self.root_cert = root_cert;
+                        }
+                        *stamps = current;
+                        changed = true;
+                    }
+                    (Err(err), _) | (_, Err(err)) => {
+                        tracing::warn!("failed to reload TLS certificate files: {}", err);
+                    }
+                }
+            }
+        }
+        if changed {
+            self.connector = build(self.identity_pem.as_ref(), self.root_cert.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl<C, D> Service<Uri> for TlsReloadConnector<C, D>
+where
+    C: Send + 'static,
+    D: Service<Uri> + Send + 'static,
+    D::Error: Into<BoxError>,
+    D::Future: Send,
+{
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+    type Response = D::Response;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The inner connector is driven to readiness for each connection in `call`.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let state = self.state.clone();
+        let build = self.build.clone();
+        let select = self.select;
+        Box::pin(async move {
+            let connector = {
+                let mut state = state.lock().await;
+                state.reload(&build).await?;
+                select(&state.connector)
+            };
+            connector.oneshot(dst).await.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use chrono::{Duration, Utc};
+
+    use crate::{client::auth::ExecCredentialStatus, config::ExecConfig};
+
+    fn exec_printing(status: &str) -> ExecConfig {
+        ExecConfig {
+            api_version: Some("client.authentication.k8s.io/v1beta1".into()),
+            command: "echo".into(),
+            args: Some(vec![format!(
+                r#"{{"kind":"ExecCredential","apiVersion":"client.authentication.k8s.io/v1beta1","status":{}}}"#,
+                status
+            )]),
+            env: None,
+            provide_cluster_info: None,
+            cluster: None,
+            other: Default::default(),
+        }
+    }
+
+    // Fake connector that responds with the PEM it was built with
+    #[derive(Clone)]
+    struct PemConnector(String);
+
+    impl Service<Uri> for PemConnector {
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<String, Infallible>>;
+        type Response = String;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _dst: Uri) -> Self::Future {
+            futures::future::ready(Ok(self.0.clone()))
+        }
+    }
+
+    fn build(pem: Option<&Vec<u8>>, _root_cert: Option<&Vec<Vec<u8>>>) -> Result<PemConnector> {
+        Ok(PemConnector(
+            String::from_utf8(pem.cloned().unwrap_or_default()).unwrap(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn rebuilds_connector_with_refreshed_certificate() {
+        let expiry = (Utc::now() + Duration::hours(1)).to_rfc3339();
+        let exec = exec_printing(&format!(
+            r#"{{"clientCertificateData":"new-cert","clientKeyData":"new-key","expirationTimestamp":"{}"}}"#,
+            expiry
+        ));
+        let expired: ExecCredentialStatus = serde_json::from_value(serde_json::json!({
+            "clientCertificateData": "old-cert",
+            "clientKeyData": "old-key",
+            "expirationTimestamp": Utc::now().to_rfc3339(),
+        }))
+        .unwrap();
+        let identity = ExecIdentity::from_status(&exec, &expired).unwrap();
+
+        let mut connector = TlsReloadConnector::new(build, None, None, identity, None).unwrap();
+        let uri = Uri::from_static("https://example.com");
+        let pem = connector.ready().await.unwrap().call(uri.clone()).await.unwrap();
+        assert_eq!(pem, "new-key\nnew-cert");
+        // The refreshed certificate is used until it expires
+        let pem = connector.ready().await.unwrap().call(uri).await.unwrap();
+        assert_eq!(pem, "new-key\nnew-cert");
+    }
+
+    #[tokio::test]
+    async fn selected_connectors_share_identity() {
+        let expiry = (Utc::now() + Duration::hours(1)).to_rfc3339();
+        let exec = exec_printing(&format!(
+            r#"{{"clientCertificateData":"new-cert","clientKeyData":"new-key","expirationTimestamp":"{}"}}"#,
+            expiry
+        ));
+        let expired: ExecCredentialStatus = serde_json::from_value(serde_json::json!({
+            "clientCertificateData": "old-cert",
+            "clientKeyData": "old-key",
+            "expirationTimestamp": Utc::now().to_rfc3339(),
+        }))
+        .unwrap();
+        let identity = ExecIdentity::from_status(&exec, &expired).unwrap();
+        let build_pair = |pem: Option<&Vec<u8>>, root_cert: Option<&Vec<Vec<u8>>>| {
+            Ok((build(pem, root_cert)?, PemConnector("other".into())))
+        };
+
+        let connectors = TlsReloadConnector::new(build_pair, None, None, identity, None).unwrap();
+        let first = connectors.select(|(first, _)| first.clone());
+        let second = connectors.select(|(_, second)| second.clone());
+        let uri = Uri::from_static("https://example.com");
+        assert_eq!(first.oneshot(uri.clone()).await.unwrap(), "new-key\nnew-cert");
+        assert_eq!(second.oneshot(uri).await.unwrap(), "other");
+    }
+
+    #[tokio::test]
+    async fn configured_certificate_takes_precedence() {
+        let exec = exec_printing("{}");
+        let status: ExecCredentialStatus = serde_json::from_value(serde_json::json!({
+            "clientCertificateData": "exec-cert",
+            "clientKeyData": "exec-key",
+        }))
+        .unwrap();
+        let identity = ExecIdentity::from_status(&exec, &status).unwrap();
+        let pem = b"configured".to_vec();
+
+        let connector = TlsReloadConnector::new(build, Some(&pem), None, identity, None).unwrap();
+        let pem = connector
+            .oneshot(Uri::from_static("https://example.com"))
+            .await
+            .unwrap();
+        assert_eq!(pem, "configured");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rebuilds_connector_when_files_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert, key) = (dir.path().join("tls.crt"), dir.path().join("tls.key"));
+        std::fs::write(&cert, "cert-1").unwrap();
+        std::fs::write(&key, "key-1").unwrap();
+        let files = TlsFiles {
+            client_certificate: Some(cert.clone()),
+            client_key: Some(key.clone()),
+            certificate_authority: None,
+        };
+        let pem = files.identity_pem().unwrap().unwrap();
+
+        let connector = TlsReloadConnector::new(build, Some(&pem), None, None, Some(&files)).unwrap();
+        let uri = Uri::from_static("https://example.com");
+        assert_eq!(
+            connector.clone().oneshot(uri.clone()).await.unwrap(),
+            "key-1\ncert-1\n"
+        );
+
+        // Rotated files of the same size are picked up at the next check
+        std::fs::write(&cert, "cert-2").unwrap();
+        std::fs::write(&key, "key-2").unwrap();
+        assert_eq!(
+            connector.clone().oneshot(uri.clone()).await.unwrap(),
+            "key-1\ncert-1\n"
+        );
+        tokio::time::advance(FILES_CHECK_INTERVAL).await;
+        assert_eq!(
+            connector.clone().oneshot(uri.clone()).await.unwrap(),
+            "key-2\ncert-2\n"
+        );
+
+        // Unreadable files keep the previous certificate
+        std::fs::remove_file(&key).unwrap();
+        tokio::time::advance(FILES_CHECK_INTERVAL).await;
+        assert_eq!(connector.oneshot(uri).await.unwrap(), "key-2\ncert-2\n");
+    }
+}
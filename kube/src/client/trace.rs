@@ -0,0 +1,252 @@
+//! Spans for apiserver requests and trace context propagation.
+use std::time::Duration;
+
+use http::{HeaderMap, Method, Request, Response};
+use hyper::Body;
+use tracing::Span;
+
+// Add the trace context of `span` to `headers` with the global propagator, keeping headers that are already set.
+#[cfg(feature = "otel")]
+pub(crate) fn inject(span: &Span, headers: &mut HeaderMap) {
+    use http::header::{HeaderName, HeaderValue};
+    use opentelemetry::{global, propagation::Injector};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+    impl Injector for HeaderInjector<'_> {
+        fn set(&mut self, key: &str, value: String) {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(key.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                self.0.entry(name).or_insert(value);
+            }
+        }
+    }
+
+    // The request span is disabled below `DEBUG`, continue the trace of the caller instead
+    let cx = if span.is_disabled() {
+        Span::current().context()
+    } else {
+        span.context()
+    };
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&cx, &mut HeaderInjector(headers))
+    });
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn inject(_span: &Span, _headers: &mut HeaderMap) {}
+
+/// The Kubernetes verb and target of a request, parsed from its method and URL.
+#[derive(Debug, Default, PartialEq)]
+struct Target<'a> {
+    verb: &'a str,
+    // `plural` or `plural/subresource`
+    resource: String,
+    namespace: Option<&'a str>,
+    name: Option<&'a str>,
+}
+
+impl<'a> Target<'a> {
+    fn parse(method: &Method, path: &'a str, query: Option<&str>) -> Option<Self> {
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        let rest = match segments.as_slice() {
+            ["api", _version, rest @ ..] | ["apis", _, _version, rest @ ..] => rest,
+            _ => return None,
+        };
+        let (namespace, rest) = match rest {
+            ["namespaces", namespace, rest @ ..] if !rest.is_empty() && !is_namespace_subresource(rest) => {
+                (Some(*namespace), rest)
+            }
+            _ => (None, rest),
+        };
+        let (plural, name, subresource) = match rest {
+            [plural] => (*plural, None, None),
+            [plural, name] => (*plural, Some(*name), None),
+            [plural, name, subresource] => (*plural, Some(*name), Some(*subresource)),
+            _ => return None,
+        };
+        let watch = query
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .any(|p| p == "watch=true" || p == "watch=1");
+        let verb = match (method, name) {
+            (&Method::GET, _) if watch => "watch",
+            (&Method::GET, Some(_)) => "get",
+            (&Method::GET, None) => "list",
+            (&Method::POST, _) => "create",
+            (&Method::PUT, _) => "replace",
+            (&Method::PATCH, _) => "patch",
+            (&Method::DELETE, Some(_)) => "delete",
+            (&Method::DELETE, None) => "delete_collection",
+            _ => return None,
+        };
+        Some(Self {
+            verb,
+            resource: subresource.map_or_else(|| plural.to_owned(), |sub| format!("{}/{}", plural, sub)),
+            namespace,
+            name,
+        })
+    }
+}
+
+// `namespaces/{name}/status` and `namespaces/{name}/finalize` are subresources of a namespace
+fn is_namespace_subresource(rest: &[&str]) -> bool {
+    matches!(rest, ["status"] | ["finalize"])
+}
+
+// Attribute names follow [Semantic Conventions].
+// [Semantic Conventions]: https://github.com/open-telemetry/opentelemetry-specification/blob/main/specification/trace/semantic_conventions/http.md
+pub(crate) fn make_span(req: &Request<Body>) -> Span {
+    let target = Target::parse(req.method(), req.uri().path(), req.uri().query());
+    let name = match &target {
+        Some(target) => format!("{} {}", target.verb, target.resource),
+        None => format!("{} {}", req.method(), req.uri().path()),
+    };
+    let span = tracing::debug_span!(
+        "HTTP",
+        http.method = %req.method(),
+        http.url = %req.uri(),
+        http.status_code = tracing::field::Empty,
+        http.latency_ms = tracing::field::Empty,
+        k8s.verb = tracing::field::Empty,
+        k8s.resource = tracing::field::Empty,
+        k8s.namespace = tracing::field::Empty,
+        k8s.name = tracing::field::Empty,
+        otel.name = %name,
+        otel.kind = "client",
+        otel.status_code = tracing::field::Empty,
+    );
+    if let Some(target) = target {
+        span.record("k8s.verb", target.verb);
+        span.record("k8s.resource", target.resource.as_str());
+        if let Some(namespace) = target.namespace {
+            span.record("k8s.namespace", namespace);
+        }
+        if let Some(name) = target.name {
+            span.record("k8s.name", name);
+        }
+    }
+    span
+}
+
+pub(crate) fn on_response(res: &Response<Body>, latency: Duration, span: &Span) {
+    let status = res.status();
+    span.record("http.status_code", status.as_u16());
+    span.record("http.latency_ms", latency.as_millis() as u64);
+    if status.is_client_error() || status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(method: Method, uri: &str) -> Option<Target<'_>> {
+        let (path, query) = match uri.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (uri, None),
+        };
+        Target::parse(&method, path, query)
+    }
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!(
+            target(Method::GET, "/api/v1/namespaces/ns/pods"),
+            Some(Target {
+                verb: "list",
+                resource: "pods".into(),
+                namespace: Some("ns"),
+                name: None,
+            })
+        );
+        assert_eq!(
+            target(
+                Method::PATCH,
+                "/apis/apps/v1/namespaces/ns/deployments/web/status"
+            ),
+            Some(Target {
+                verb: "patch",
+                resource: "deployments/status".into(),
+                namespace: Some("ns"),
+                name: Some("web"),
+            })
+        );
+        assert_eq!(
+            target(Method::GET, "/api/v1/namespaces?watch=true&resourceVersion=1"),
+            Some(Target {
+                verb: "watch",
+                resource: "namespaces".into(),
+                ..Target::default()
+            })
+        );
+        assert_eq!(
+            target(Method::PUT, "/api/v1/namespaces/ns/finalize"),
+            Some(Target {
+                verb: "replace",
+                resource: "namespaces/finalize".into(),
+                namespace: None,
+                name: Some("ns"),
+            })
+        );
+        assert_eq!(
+            target(Method::DELETE, "/apis/rbac.authorization.k8s.io/v1/clusterroles"),
+            Some(Target {
+                verb: "delete_collection",
+                resource: "clusterroles".into(),
+                ..Target::default()
+            })
+        );
+        assert_eq!(target(Method::GET, "/version"), None);
+        assert_eq!(target(Method::GET, "/apis/apps/v1"), None);
+    }
+
+    #[tokio::test]
+    async fn request_spans_are_children_of_the_caller() {
+        use std::sync::{Arc, Mutex};
+        use tracing::{span, Instrument, Subscriber};
+        use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer, Registry};
+
+        // Names of new spans and their parents
+        type Names = Vec<(&'static str, Option<&'static str>)>;
+
+        #[derive(Clone, Default)]
+        struct Spans(Arc<Mutex<Names>>);
+
+        impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Spans {
+            fn new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+                let span = ctx.span(id).unwrap();
+                let parent = span.parent().map(|parent| parent.name());
+                self.0.lock().unwrap().push((span.name(), parent));
+            }
+        }
+
+        let spans = Spans::default();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(spans.clone()));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let service = tower::service_fn(move |_req: Request<Body>| {
+            tx.send(Span::current().metadata().map(|m| m.name())).unwrap();
+            async { Response::builder().body(Body::from("{}")) }
+        });
+        let client = crate::Client::new(service, "default");
+        let req = Request::get("/api/v1/namespaces/default/pods/p")
+            .body(vec![])
+            .unwrap();
+
+        client
+            .request_text(req)
+            .instrument(tracing::info_span!("reconciling object"))
+            .await
+            .unwrap();
+        // The service is called by the `Buffer` worker within the request span
+        assert_eq!(rx.recv().await.unwrap(), Some("HTTP"));
+        assert_eq!(*spans.0.lock().unwrap(), vec![
+            ("reconciling object", None),
+            ("HTTP", Some("reconciling object"))
+        ]);
+    }
+}
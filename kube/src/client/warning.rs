@@ -0,0 +1,215 @@
+//! Handling of `Warning` headers returned by the apiserver.
+use std::fmt;
+
+use http::{header::WARNING, HeaderMap};
+
+/// A warning returned by the apiserver in a `Warning` header.
+///
+/// The apiserver uses these for deprecated APIs and for fields it accepted but ignored,
+/// always with the code `299` and the agent `-`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    /// Warning code, `299` for miscellaneous persistent warnings
+    pub code: u16,
+    /// Host name or pseudonym of the server adding the warning
+    pub agent: String,
+    /// The warning message
+    pub text: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Warning {
+    /// Parse the warnings in `headers`, skipping any malformed values.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Vec<Warning> {
+        headers
+            .get_all(WARNING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| {
+                parse_warnings(value).unwrap_or_else(|| {
+                    tracing::debug!(value, "ignoring malformed warning header");
+                    Vec::new()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Receives the warnings returned by the apiserver.
+///
+/// Implemented for closures taking a `&Warning`. Set one with [`Client::with_warning_handler`].
+///
+/// [`Client::with_warning_handler`]: crate::Client::with_warning_handler
+pub trait WarningHandler: Send + Sync {
+    /// Called for every warning in a response.
+    fn handle_warning(&self, warning: &Warning);
+}
+
+impl<F> WarningHandler for F
+where
+    F: Fn(&Warning) + Send + Sync,
+{
+    fn handle_warning(&self, warning: &Warning) {
+        self(warning)
+    }
+}
+
+/// The default [`WarningHandler`], logging every warning with `tracing::warn!`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogWarnings;
+
+impl WarningHandler for LogWarnings {
+    fn handle_warning(&self, warning: &Warning) {
+        tracing::warn!(code = warning.code, agent = %warning.agent, "{}", warning.text);
+    }
+}
+
+// Parses a comma separated list of `code SP agent SP quoted-text [SP quoted-date]` (RFC 7234 section 5.5).
+fn parse_warnings(mut value: &str) -> Option<Vec<Warning>> {
+    let mut warnings = Vec::new();
+    loop {
+        value = value.trim_start();
+        if value.is_empty() {
+            return Some(warnings);
+        }
+
+        let (code, rest) = value.split_once(' ')?;
+        if code.len() != 3 {
+            return None;
+        }
+        let code = code.parse().ok()?;
+        let (agent, rest) = rest.split_once(' ')?;
+        let (text, mut rest) = parse_quoted(rest)?;
+        warnings.push(Warning {
+            code,
+            agent: agent.to_owned(),
+            text,
+        });
+
+        if let Some(date) = rest.strip_prefix(' ').filter(|r| r.starts_with('"')) {
+            rest = parse_quoted(date)?.1;
+        }
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Some(warnings);
+        }
+        value = rest.strip_prefix(',')?;
+    }
+}
+
+// Parses a quoted string with backslash escapes, returning it unescaped with the remaining input.
+fn parse_quoted(value: &str) -> Option<(String, &str)> {
+    let mut chars = value.strip_prefix('"')?.char_indices();
+    let mut text = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((text, &value[i + 2..])),
+            '\\' => text.push(chars.next()?.1),
+            c => text.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use http::{header::HeaderValue, Request, Response, StatusCode};
+    use hyper::Body;
+    use k8s_openapi::api::core::v1::ConfigMap;
+
+    use crate::{Api, Client, Error};
+
+    fn warning(text: &str) -> Warning {
+        Warning {
+            code: 299,
+            agent: "-".into(),
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn parses_warning_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            WARNING,
+            HeaderValue::from_static(r#"299 - "v1beta1 Ingress is deprecated in v1.19+""#),
+        );
+        headers.append(
+            WARNING,
+            HeaderValue::from_static(
+                r#"299 - "unknown field \"spec.foo\"" "Sat, 25 Aug 2012 23:34:45 GMT", 299 - "second""#,
+            ),
+        );
+        assert_eq!(Warning::from_headers(&headers), vec![
+            warning("v1beta1 Ingress is deprecated in v1.19+"),
+            warning(r#"unknown field "spec.foo""#),
+            warning("second"),
+        ]);
+    }
+
+    #[test]
+    fn skips_malformed_warning_headers() {
+        for value in &[
+            r#"299 - unquoted"#,
+            r#"299 - "unterminated"#,
+            r#"29 - "short code""#,
+            "299",
+        ] {
+            assert_eq!(parse_warnings(value), None, "{}", value);
+        }
+        let mut headers = HeaderMap::new();
+        headers.append(WARNING, HeaderValue::from_static("bogus"));
+        headers.append(WARNING, HeaderValue::from_static(r#"299 - "valid""#));
+        assert_eq!(Warning::from_headers(&headers), vec![warning("valid")]);
+    }
+
+    fn client_warning(status: StatusCode) -> Client {
+        let service = tower::service_fn(move |_: Request<Body>| async move {
+            Response::builder()
+                .status(status)
+                .header(WARNING, r#"299 - "deprecated""#)
+                .body(Body::from(
+                    r#"{"apiVersion":"v1","kind":"ConfigMap","metadata":{"name":"test"}}"#,
+                ))
+        });
+        Client::new(service, "default")
+    }
+
+    #[tokio::test]
+    async fn passes_warnings_to_handler() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let client = client_warning(StatusCode::OK).with_warning_handler({
+            let seen = seen.clone();
+            move |w: &Warning| seen.lock().unwrap().push(w.clone())
+        });
+        // Handlers are kept for impersonated clients
+        let client = client.impersonate("someone", &[]).unwrap();
+        let api: Api<ConfigMap> = Api::default_namespaced(client);
+        api.get("test").await.unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![warning("deprecated")]);
+    }
+
+    #[tokio::test]
+    async fn fails_successful_requests_on_warnings() {
+        let api: Api<ConfigMap> =
+            Api::default_namespaced(client_warning(StatusCode::OK).warnings_as_errors());
+        match api.get("test").await {
+            Err(Error::Warnings(warnings)) => assert_eq!(warnings, vec![warning("deprecated")]),
+            res => panic!("expected warnings error, got {:?}", res),
+        }
+
+        // Errors from the apiserver are returned as they are
+        let api: Api<ConfigMap> =
+            Api::default_namespaced(client_warning(StatusCode::NOT_FOUND).warnings_as_errors());
+        assert!(matches!(api.get("test").await, Err(Error::Api(_))));
+    }
+}
@@ -1,7 +1,13 @@
 #![allow(missing_docs)]
-use crate::{config::utils, error::ConfigError, Result};
+use crate::{config::utils, error::ConfigError, Error, Result};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 
 /// [`Kubeconfig`] represents information on how to connect to a remote Kubernetes cluster
 ///
@@ -19,7 +25,7 @@ pub struct Kubeconfig {
     /// Referencable names to cluster configs
     pub clusters: Vec<NamedCluster>,
     /// Referencable names to user configs
-    #[serde(rename = "users")]
+    #[serde(default, rename = "users")]
     pub auth_infos: Vec<NamedAuthInfo>,
     /// Referencable names to context configs
     pub contexts: Vec<NamedContext>,
@@ -33,6 +39,15 @@ pub struct Kubeconfig {
     pub kind: Option<String>,
     #[serde(rename = "apiVersion")]
     pub api_version: Option<String>,
+
+    /// The files this config was read from, in the order they were merged
+    ///
+    /// [`Kubeconfig::write`] saves new entries and `current-context` to the first one.
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// Preferences stores extensions for cli.
@@ -40,6 +55,9 @@ pub struct Kubeconfig {
 pub struct Preferences {
     pub colors: Option<bool>,
     pub extensions: Option<Vec<NamedExtension>>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// NamedExtention associates name with extension.
@@ -54,6 +72,12 @@ pub struct NamedExtension {
 pub struct NamedCluster {
     pub name: String,
     pub cluster: Cluster,
+    /// The file this entry was read from, where [`Kubeconfig::write`] saves it
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// Cluster stores information to connect Kubernetes cluster.
@@ -63,6 +87,9 @@ pub struct Cluster {
     pub server: String,
     #[serde(rename = "insecure-skip-tls-verify")]
     pub insecure_skip_tls_verify: Option<bool>,
+    /// Name used to verify the server certificate instead of the host in `server`.
+    #[serde(rename = "tls-server-name")]
+    pub tls_server_name: Option<String>,
     /// The path to a cert file for the certificate authority.
     #[serde(rename = "certificate-authority")]
     pub certificate_authority: Option<String>,
@@ -74,6 +101,9 @@ pub struct Cluster {
     pub proxy_url: Option<String>,
     /// Additional information for extenders so that reads and writes don't clobber unknown fields
     pub extensions: Option<Vec<NamedExtension>>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// NamedAuthInfo associates name with authentication.
@@ -82,6 +112,12 @@ pub struct NamedAuthInfo {
     pub name: String,
     #[serde(rename = "user")]
     pub auth_info: AuthInfo,
+    /// The file this entry was read from, where [`Kubeconfig::write`] saves it
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// AuthInfo stores information to tell cluster who you are.
@@ -118,6 +154,9 @@ pub struct AuthInfo {
     /// The groups to imperonate.
     #[serde(rename = "as-groups")]
     pub impersonate_groups: Option<Vec<String>>,
+    /// Additional information to impersonate, keyed by extra name.
+    #[serde(rename = "as-user-extra")]
+    pub impersonate_extra: Option<HashMap<String, Vec<String>>>,
 
     /// Specifies a custom authentication plugin for the kubernetes cluster.
     #[serde(rename = "auth-provider")]
@@ -125,6 +164,9 @@ pub struct AuthInfo {
 
     /// Specifies a custom exec-based authentication plugin for the kubernetes cluster.
     pub exec: Option<ExecConfig>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// AuthProviderConfig stores auth for specified cloud provider.
@@ -132,6 +174,9 @@ pub struct AuthInfo {
 pub struct AuthProviderConfig {
     pub name: String,
     pub config: HashMap<String, String>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// ExecConfig stores credential-plugin configuration.
@@ -150,6 +195,63 @@ pub struct ExecConfig {
     ///
     /// TODO: These are unioned with the host's environment, as well as variables client-go uses to pass argument to the plugin.
     pub env: Option<Vec<HashMap<String, String>>>,
+    /// Whether to pass the cluster to the command in `KUBERNETES_EXEC_INFO`.
+    #[serde(rename = "provideClusterInfo")]
+    pub provide_cluster_info: Option<bool>,
+    /// The cluster passed to the command when `provide_cluster_info` is set.
+    ///
+    /// This is populated when loading the kubeconfig.
+    #[serde(skip)]
+    pub cluster: Option<ExecAuthCluster>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// Cluster information passed to exec plugins in `KUBERNETES_EXEC_INFO`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExecAuthCluster {
+    /// The address of the kubernetes cluster (https://hostname:port).
+    pub server: String,
+    /// Whether the server's certificate is not checked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insecure_skip_tls_verify: Option<bool>,
+    /// Name used to verify the server certificate instead of the host in `server`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_server_name: Option<String>,
+    /// Base64 encoded PEM certificate authority certificates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate_authority_data: Option<String>,
+    /// URL to the proxy to be used for all requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_url: Option<String>,
+    /// The `client.authentication.k8s.io/exec` extension of the cluster.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<serde_json::Value>,
+}
+
+// Name of the cluster extension passed to exec plugins as configuration
+const EXEC_CLUSTER_EXTENSION: &str = "client.authentication.k8s.io/exec";
+
+impl TryFrom<&Cluster> for ExecAuthCluster {
+    type Error = Error;
+
+    fn try_from(cluster: &Cluster) -> Result<Self> {
+        Ok(Self {
+            server: cluster.server.clone(),
+            insecure_skip_tls_verify: cluster.insecure_skip_tls_verify,
+            tls_server_name: cluster.tls_server_name.clone(),
+            certificate_authority_data: cluster.load_certificate_authority()?.map(base64::encode),
+            proxy_url: cluster.proxy_url.clone(),
+            config: cluster.extensions.as_ref().and_then(|extensions| {
+                extensions
+                    .iter()
+                    .find(|extension| extension.name == EXEC_CLUSTER_EXTENSION)
+                    .map(|extension| extension.extension.clone())
+            }),
+        })
+    }
 }
 
 /// NamedContext associates name with context.
@@ -157,6 +259,12 @@ pub struct ExecConfig {
 pub struct NamedContext {
     pub name: String,
     pub context: Context,
+    /// The file this entry was read from, where [`Kubeconfig::write`] saves it
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 /// Context stores tuple of cluster and user information.
@@ -165,11 +273,15 @@ pub struct Context {
     /// Name of the cluster for this context
     pub cluster: String,
     /// Name of the `AuthInfo` for this context
+    #[serde(default)]
     pub user: String,
     /// The default namespace to use on unspecified requests
     pub namespace: Option<String>,
     /// Additional information for extenders so that reads and writes don't clobber unknown fields
     pub extensions: Option<Vec<NamedExtension>>,
+    /// Fields unknown to this version, which are kept when the config is written
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_yaml::Value>,
 }
 
 const KUBECONFIG: &str = "KUBECONFIG";
@@ -178,54 +290,66 @@ const KUBECONFIG: &str = "KUBECONFIG";
 impl Kubeconfig {
     /// Read a Config from an arbitrary location
     pub fn read_from<P: AsRef<Path>>(path: P) -> Result<Kubeconfig> {
-        let data = fs::read_to_string(&path).map_err(|source| ConfigError::ReadFile {
-            path: path.as_ref().into(),
-            source,
-        })?;
-        // support multiple documents
-        let mut documents: Vec<Kubeconfig> = vec![];
-        for doc in serde_yaml::Deserializer::from_str(&data) {
-            let value = serde_yaml::Value::deserialize(doc).map_err(ConfigError::ParseYaml)?;
-            let kconf = serde_yaml::from_value(value).map_err(ConfigError::ParseYaml)?;
-            documents.push(kconf)
-        }
-
+        let path = path.as_ref();
+        let mut config = Self::read_file(path)?;
         // Remap all files we read to absolute paths.
-        let mut merged_docs = None;
-        for mut config in documents {
-            if let Some(dir) = path.as_ref().parent() {
-                for named in config.clusters.iter_mut() {
-                    if let Some(path) = &named.cluster.certificate_authority {
-                        if let Some(abs_path) = to_absolute(dir, path) {
-                            named.cluster.certificate_authority = Some(abs_path);
-                        }
+        if let Some(dir) = path.parent() {
+            for named in config.clusters.iter_mut() {
+                if let Some(path) = &named.cluster.certificate_authority {
+                    if let Some(abs_path) = to_absolute(dir, path) {
+                        named.cluster.certificate_authority = Some(abs_path);
                     }
                 }
-                for named in config.auth_infos.iter_mut() {
-                    if let Some(path) = &named.auth_info.client_certificate {
-                        if let Some(abs_path) = to_absolute(dir, path) {
-                            named.auth_info.client_certificate = Some(abs_path);
-                        }
+            }
+            for named in config.auth_infos.iter_mut() {
+                if let Some(path) = &named.auth_info.client_certificate {
+                    if let Some(abs_path) = to_absolute(dir, path) {
+                        named.auth_info.client_certificate = Some(abs_path);
                     }
-                    if let Some(path) = &named.auth_info.client_key {
-                        if let Some(abs_path) = to_absolute(dir, path) {
-                            named.auth_info.client_key = Some(abs_path);
-                        }
+                }
+                if let Some(path) = &named.auth_info.client_key {
+                    if let Some(abs_path) = to_absolute(dir, path) {
+                        named.auth_info.client_key = Some(abs_path);
                     }
-                    if let Some(path) = &named.auth_info.token_file {
-                        if let Some(abs_path) = to_absolute(dir, path) {
-                            named.auth_info.token_file = Some(abs_path);
-                        }
+                }
+                if let Some(path) = &named.auth_info.token_file {
+                    if let Some(abs_path) = to_absolute(dir, path) {
+                        named.auth_info.token_file = Some(abs_path);
                     }
                 }
             }
+        }
+        for named in config.clusters.iter_mut() {
+            named.source = Some(path.to_path_buf());
+        }
+        for named in config.auth_infos.iter_mut() {
+            named.source = Some(path.to_path_buf());
+        }
+        for named in config.contexts.iter_mut() {
+            named.source = Some(path.to_path_buf());
+        }
+        config.files = vec![path.to_path_buf()];
+        Ok(config)
+    }
+
+    // Reads and merges the documents of a file as they are, without remapping paths
+    fn read_file(path: &Path) -> Result<Kubeconfig> {
+        let data = fs::read_to_string(path).map_err(|source| ConfigError::ReadFile {
+            path: path.into(),
+            source,
+        })?;
+        // support multiple documents
+        let mut merged_docs = None;
+        for doc in serde_yaml::Deserializer::from_str(&data) {
+            let value = serde_yaml::Value::deserialize(doc).map_err(ConfigError::ParseYaml)?;
+            let config = serde_yaml::from_value(value).map_err(ConfigError::ParseYaml)?;
             if let Some(c) = merged_docs {
                 merged_docs = Some(Kubeconfig::merge(c, config)?);
             } else {
                 merged_docs = Some(config);
             }
         }
-        let config = merged_docs.ok_or_else(|| ConfigError::EmptyKubeconfig(path.as_ref().to_path_buf()))?;
+        let config = merged_docs.ok_or_else(|| ConfigError::EmptyKubeconfig(path.to_path_buf()))?;
         Ok(config)
     }
 
@@ -247,23 +371,189 @@ impl Kubeconfig {
     ///
     /// Panics if `KUBECONFIG` value contains the NUL character.
     pub fn from_env() -> Result<Option<Self>> {
-        match std::env::var_os(KUBECONFIG) {
-            Some(value) => {
-                let paths = std::env::split_paths(&value)
-                    .filter(|p| !p.as_os_str().is_empty())
-                    .collect::<Vec<_>>();
-                if paths.is_empty() {
-                    return Ok(None);
+        match Self::paths_from_env() {
+            Some(paths) => Ok(Some(Self::read_paths(&paths)?)),
+            None => Ok(None),
+        }
+    }
+
+    // The non-empty paths in `KUBECONFIG`, if any
+    pub(crate) fn paths_from_env() -> Option<Vec<PathBuf>> {
+        let value = std::env::var_os(KUBECONFIG)?;
+        let paths = std::env::split_paths(&value)
+            .filter(|p| !p.as_os_str().is_empty())
+            .collect::<Vec<_>>();
+        Some(paths).filter(|paths| !paths.is_empty())
+    }
+
+    // Reads and merges kubeconfig files, with the first file to set a value winning
+    pub(crate) fn read_paths(paths: &[PathBuf]) -> Result<Self> {
+        paths.iter().try_fold(Kubeconfig::default(), |m, p| {
+            Kubeconfig::read_from(p).and_then(|c| m.merge(c))
+        })
+    }
+
+    /// Add a cluster, or replace the cluster with the same name, like `kubectl config set-cluster`.
+    pub fn set_cluster(&mut self, name: &str, cluster: Cluster) {
+        match self.clusters.iter_mut().find(|named| named.name == name) {
+            Some(named) => named.cluster = cluster,
+            None => self.clusters.push(NamedCluster {
+                name: name.to_owned(),
+                cluster,
+                source: None,
+                other: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Add a user, or replace the user with the same name, like `kubectl config set-credentials`.
+    pub fn set_auth_info(&mut self, name: &str, auth_info: AuthInfo) {
+        match self.auth_infos.iter_mut().find(|named| named.name == name) {
+            Some(named) => named.auth_info = auth_info,
+            None => self.auth_infos.push(NamedAuthInfo {
+                name: name.to_owned(),
+                auth_info,
+                source: None,
+                other: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Add a context, or replace the context with the same name, like `kubectl config set-context`.
+    pub fn set_context(&mut self, name: &str, context: Context) {
+        match self.contexts.iter_mut().find(|named| named.name == name) {
+            Some(named) => named.context = context,
+            None => self.contexts.push(NamedContext {
+                name: name.to_owned(),
+                context,
+                source: None,
+                other: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Remove the cluster with the given name, like `kubectl config delete-cluster`.
+    pub fn remove_cluster(&mut self, name: &str) -> Option<Cluster> {
+        let index = self.clusters.iter().position(|named| named.name == name)?;
+        Some(self.clusters.remove(index).cluster)
+    }
+
+    /// Remove the user with the given name, like `kubectl config delete-user`.
+    pub fn remove_auth_info(&mut self, name: &str) -> Option<AuthInfo> {
+        let index = self.auth_infos.iter().position(|named| named.name == name)?;
+        Some(self.auth_infos.remove(index).auth_info)
+    }
+
+    /// Remove the context with the given name, like `kubectl config delete-context`.
+    ///
+    /// `current-context` is left as it is, even when it names the removed context.
+    pub fn remove_context(&mut self, name: &str) -> Option<Context> {
+        let index = self.contexts.iter().position(|named| named.name == name)?;
+        Some(self.contexts.remove(index).context)
+    }
+
+    /// Switch `current-context` to an existing context, like `kubectl config use-context`.
+    pub fn use_context(&mut self, name: &str) -> Result<()> {
+        if !self.contexts.iter().any(|named| named.name == name) {
+            return Err(ConfigError::LoadContext {
+                context_name: name.to_owned(),
+            }
+            .into());
+        }
+        self.current_context = Some(name.to_owned());
+        Ok(())
+    }
+
+    /// Serialize the config to YAML, leaving out unset fields.
+    pub fn to_yaml(&self) -> Result<String> {
+        // Going through `serde_json::Value` sorts the keys, so the output is stable
+        let mut value = serde_json::to_value(self).map_err(ConfigError::SerializeKubeconfig)?;
+        remove_nulls(&mut value);
+        Ok(serde_yaml::to_string(&value).map_err(ConfigError::SerializeYaml)?)
+    }
+
+    /// Write the whole config to a single file, replacing it.
+    ///
+    /// Paths are written as they are, so files read by [`Kubeconfig::read_from`] are referenced by absolute paths.
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let _lock = FileLock::acquire(path)?;
+        write_atomic(path, &self.to_yaml()?)
+    }
+
+    /// Save the config back to the files it was read from, like `kubectl config` does.
+    ///
+    /// Each cluster, user and context is written to its `source` file, and entries without one
+    /// (added by [`Kubeconfig::set_cluster`] and friends) to the first of [`Kubeconfig::files`],
+    /// or to `~/.kube/config` if the config was not read from a file.
+    /// `current-context` is written to the first file that sets it.
+    ///
+    /// Entries that are no longer in the config are removed from the files it was read from,
+    /// except for entries that are shadowed by an entry with the same name in an earlier file.
+    /// Other fields, like `preferences`, are kept as they are in each file, and so are fields unknown
+    /// to this version of the config. Comments in the files are not kept.
+    ///
+    /// All files are locked while they are updated, and each one is replaced atomically.
+    pub fn write(&self) -> Result<()> {
+        let mut files = self.files.clone();
+        if files.is_empty() {
+            files.push(utils::default_kube_path().ok_or(ConfigError::NoKubeconfigPath)?);
+        }
+        let sources = (self.clusters.iter().map(|named| &named.source))
+            .chain(self.auth_infos.iter().map(|named| &named.source))
+            .chain(self.contexts.iter().map(|named| &named.source));
+        for source in sources.flatten() {
+            if !files.contains(source) {
+                files.push(source.clone());
+            }
+        }
+
+        let _locks = files
+            .iter()
+            .map(|file| FileLock::acquire(file))
+            .collect::<Result<Vec<_>>>()?;
+        let mut existing = Vec::with_capacity(files.len());
+        for file in &files {
+            existing.push(match Self::read_file(file) {
+                Ok(config) => Some(config),
+                Err(Error::Kubeconfig(ConfigError::ReadFile { source, .. }))
+                    if source.kind() == io::ErrorKind::NotFound =>
+                {
+                    None
                 }
+                Err(err) => return Err(err),
+            });
+        }
+        let context_file = existing
+            .iter()
+            .position(|config| matches!(config, Some(c) if c.current_context.is_some()))
+            .unwrap_or(0);
 
-                let merged = paths.iter().try_fold(Kubeconfig::default(), |m, p| {
-                    Kubeconfig::read_from(p).and_then(|c| m.merge(c))
-                })?;
-                Ok(Some(merged))
+        for (index, (file, config)) in files.iter().zip(existing).enumerate() {
+            let before = config.as_ref().map(Kubeconfig::to_yaml).transpose()?;
+            let mut config = config.unwrap_or_else(|| Kubeconfig {
+                kind: Some("Config".into()),
+                api_version: Some("v1".into()),
+                ..Kubeconfig::default()
+            });
+            let target = WriteTarget {
+                file,
+                is_first: index == 0,
+                was_read: self.files.contains(file),
+            };
+            target.update(&mut config.clusters, &self.clusters);
+            target.update(&mut config.auth_infos, &self.auth_infos);
+            target.update(&mut config.contexts, &self.contexts);
+            if index == context_file && (target.was_read || self.current_context.is_some()) {
+                config.current_context = self.current_context.clone();
             }
 
-            None => Ok(None),
+            let after = config.to_yaml()?;
+            if before.as_ref() != Some(&after) {
+                write_atomic(file, &after)?;
+            }
         }
+        Ok(())
     }
 
     /// Merge kubeconfig file according to the rules described in
@@ -294,6 +584,10 @@ impl Kubeconfig {
         append_new_named(&mut self.contexts, next.contexts, |x| &x.name);
         self.current_context = self.current_context.or(next.current_context);
         self.extensions = self.extensions.or(next.extensions);
+        for (key, value) in next.other {
+            self.other.entry(key).or_insert(value);
+        }
+        self.files.extend(next.files);
         Ok(self)
     }
 }
@@ -320,6 +614,172 @@ fn to_absolute(dir: &Path, file: &str) -> Option<String> {
     }
 }
 
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+// Entries of a kubeconfig that are written back to their source file
+trait Named: Clone {
+    fn name(&self) -> &str;
+    fn source(&self) -> Option<&Path>;
+    // Turns paths that were made absolute by `Kubeconfig::read_from` relative to `dir` again
+    fn relative_to(&mut self, _dir: &Path) {}
+}
+
+impl Named for NamedCluster {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    fn relative_to(&mut self, dir: &Path) {
+        to_relative(dir, &mut self.cluster.certificate_authority);
+    }
+}
+
+impl Named for NamedAuthInfo {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    fn relative_to(&mut self, dir: &Path) {
+        to_relative(dir, &mut self.auth_info.client_certificate);
+        to_relative(dir, &mut self.auth_info.client_key);
+        to_relative(dir, &mut self.auth_info.token_file);
+    }
+}
+
+impl Named for NamedContext {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+}
+
+// A file that `Kubeconfig::write` saves entries to
+struct WriteTarget<'a> {
+    file: &'a Path,
+    // New entries are written to the first file
+    is_first: bool,
+    // Entries are only removed from files that the config was read from
+    was_read: bool,
+}
+
+impl WriteTarget<'_> {
+    fn owns<T: Named>(&self, entry: &T) -> bool {
+        entry.source().map_or(self.is_first, |source| source == self.file)
+    }
+
+    fn update<T: Named>(&self, entries: &mut Vec<T>, config: &[T]) {
+        let dir = self.file.parent();
+        let owned = |entry: &T| {
+            let mut entry = entry.clone();
+            if let Some(dir) = dir.filter(|_| entry.source().is_some()) {
+                entry.relative_to(dir);
+            }
+            entry
+        };
+        let mut updated = Vec::with_capacity(entries.len());
+        for entry in entries.drain(..) {
+            match config.iter().find(|named| named.name() == entry.name()) {
+                Some(named) if self.owns(named) => updated.push(owned(named)),
+                Some(_) => updated.push(entry),
+                None if !self.was_read => updated.push(entry),
+                None => {}
+            }
+        }
+        for named in config.iter().filter(|named| self.owns(*named)) {
+            if !updated.iter().any(|entry| entry.name() == named.name()) {
+                updated.push(owned(named));
+            }
+        }
+        *entries = updated;
+    }
+}
+
+fn to_relative(dir: &Path, file: &mut Option<String>) {
+    let relative = file
+        .as_deref()
+        .and_then(|file| Path::new(file).strip_prefix(dir).ok())
+        .filter(|path| !path.as_os_str().is_empty())
+        .and_then(Path::to_str)
+        .map(str::to_owned);
+    if relative.is_some() {
+        *file = relative;
+    }
+}
+
+// Lock file next to a kubeconfig, like client-go's `<file>.lock`, that is removed on drop
+struct FileLock(PathBuf);
+
+impl FileLock {
+    fn acquire(file: &Path) -> Result<Self> {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+        let lock = || {
+            if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            OpenOptions::new().write(true).create_new(true).open(&path)
+        };
+        match lock() {
+            Ok(_) => Ok(Self(path)),
+            Err(source) => Err(ConfigError::LockFile { path, source }.into()),
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Replaces the file by renaming a temporary file over it, so readers never see a partial write
+fn write_atomic(path: &Path, data: &str) -> Result<()> {
+    // Write through symlinks instead of replacing them
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let write = || {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // Kubeconfigs contain credentials
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp)?;
+        if let Ok(metadata) = fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(data.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)
+    };
+    write().map_err(|source| {
+        let _ = fs::remove_file(&tmp);
+        ConfigError::WriteFile { path, source }.into()
+    })
+}
+
 impl Cluster {
     pub(crate) fn load_certificate_authority(&self) -> Result<Option<Vec<u8>>> {
         if self.certificate_authority.is_none() && self.certificate_authority_data.is_none() {
@@ -356,6 +816,8 @@ mod tests {
                     token: Some("first-token".into()),
                     ..Default::default()
                 },
+                source: None,
+                other: BTreeMap::new(),
             }],
             ..Default::default()
         };
@@ -369,6 +831,8 @@ mod tests {
                         username: Some("red-user".into()),
                         ..Default::default()
                     },
+                    source: None,
+                    other: BTreeMap::new(),
                 },
                 NamedAuthInfo {
                     name: "green-user".into(),
@@ -376,6 +840,8 @@ mod tests {
                         token: Some("new-token".into()),
                         ..Default::default()
                     },
+                    source: None,
+                    other: BTreeMap::new(),
                 },
             ],
             ..Default::default()
@@ -517,4 +983,232 @@ users:
 
         Ok(())
     }
+
+    #[test]
+    fn kubeconfig_mutations() {
+        let cluster = |server: &str| Cluster {
+            server: server.into(),
+            insecure_skip_tls_verify: None,
+            tls_server_name: None,
+            certificate_authority: None,
+            certificate_authority_data: None,
+            proxy_url: None,
+            extensions: None,
+            other: BTreeMap::new(),
+        };
+        let mut config = Kubeconfig::default();
+        config.set_cluster("a", cluster("https://a:6443"));
+        config.clusters[0].source = Some("/kube/a".into());
+        config.set_cluster("a", cluster("https://a:8443"));
+        assert_eq!(config.clusters.len(), 1);
+        assert_eq!(config.clusters[0].cluster.server, "https://a:8443");
+        // Replacing an entry keeps the file it is saved to
+        assert_eq!(config.clusters[0].source, Some("/kube/a".into()));
+
+        config.set_context("a", Context {
+            cluster: "a".into(),
+            user: "".into(),
+            namespace: None,
+            extensions: None,
+            other: BTreeMap::new(),
+        });
+        assert!(config.use_context("b").is_err());
+        config.use_context("a").unwrap();
+        assert_eq!(config.current_context.as_deref(), Some("a"));
+
+        assert!(config.remove_cluster("b").is_none());
+        assert_eq!(config.remove_cluster("a").unwrap().server, "https://a:8443");
+        assert!(config.clusters.is_empty());
+
+        let yaml = config.to_yaml().unwrap();
+        assert!(!yaml.contains("null"));
+        let parsed: Kubeconfig = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed.contexts[0].context.cluster, "a");
+        assert_eq!(parsed.current_context.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn kubeconfig_write_back_to_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        fs::write(
+            &first,
+            "apiVersion: v1
+kind: Config
+preferences:
+  colors: true
+clusters:
+- name: a
+  cluster:
+    server: https://a:6443
+    certificate-authority: ca.crt
+contexts:
+- name: a
+  context:
+    cluster: a
+    user: a
+users:
+- name: a
+  user:
+    token: first
+",
+        )
+        .unwrap();
+        fs::write(
+            &second,
+            "apiVersion: v1
+kind: Config
+clusters:
+- name: a
+  cluster:
+    server: https://shadowed:6443
+- name: b
+  cluster:
+    server: https://b:6443
+contexts: []
+current-context: a
+",
+        )
+        .unwrap();
+
+        let mut config = Kubeconfig::read_from(&first)
+            .and_then(|c| c.merge(Kubeconfig::read_from(&second)?))
+            .unwrap();
+        assert_eq!(config.files, vec![first.clone(), second.clone()]);
+        let mut cluster = config.remove_cluster("b").unwrap();
+        cluster.server = "https://c:6443".into();
+        config.set_cluster("c", cluster);
+        config.set_auth_info("a", AuthInfo {
+            token: Some("updated".into()),
+            ..AuthInfo::default()
+        });
+        config.set_context("c", Context {
+            cluster: "c".into(),
+            user: "a".into(),
+            namespace: None,
+            extensions: None,
+            other: BTreeMap::new(),
+        });
+        config.use_context("c").unwrap();
+        config.write().unwrap();
+
+        let written = Kubeconfig::read_file(&first).unwrap();
+        assert!(written.preferences.unwrap().colors.unwrap());
+        assert_eq!(written.current_context, None);
+        assert_eq!(
+            written.clusters.iter().map(|c| &c.name[..]).collect::<Vec<_>>(),
+            vec!["a", "c"]
+        );
+        // Paths made absolute when reading are relative again
+        assert_eq!(
+            written.clusters[0].cluster.certificate_authority.as_deref(),
+            Some("ca.crt")
+        );
+        assert_eq!(written.auth_infos[0].auth_info.token.as_deref(), Some("updated"));
+        assert_eq!(
+            written.contexts.iter().map(|c| &c.name[..]).collect::<Vec<_>>(),
+            vec!["a", "c"]
+        );
+
+        let written = Kubeconfig::read_file(&second).unwrap();
+        assert_eq!(written.current_context.as_deref(), Some("c"));
+        // Shadowed entries are kept, removed ones are not
+        assert_eq!(written.clusters.len(), 1);
+        assert_eq!(written.clusters[0].cluster.server, "https://shadowed:6443");
+
+        let reread = Kubeconfig::read_from(&first)
+            .and_then(|c| c.merge(Kubeconfig::read_from(&second)?))
+            .unwrap();
+        assert_eq!(reread.to_yaml().unwrap(), config.to_yaml().unwrap());
+    }
+
+    #[test]
+    fn kubeconfig_write_keeps_unknown_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        fs::write(
+            &path,
+            "apiVersion: v1
+kind: Config
+future-top: 1
+preferences:
+  future-preference: true
+clusters:
+- name: a
+  future-named-cluster: x
+  cluster:
+    server: https://a:6443
+    disable-compression: true
+contexts:
+- name: a
+  future-named-context: x
+  context:
+    cluster: a
+    user: a
+    future-context: x
+users:
+- name: a
+  future-named-user: x
+  user:
+    future-user: x
+    exec:
+      command: token-helper
+      interactiveMode: Never
+- name: b
+  user:
+    auth-provider:
+      name: oidc
+      future-provider: x
+      config: {}
+",
+        )
+        .unwrap();
+
+        let mut config = Kubeconfig::read_from(&path).unwrap();
+        assert_eq!(config.other["future-top"], serde_yaml::Value::from(1));
+        config.current_context = Some("a".into());
+        config.write().unwrap();
+
+        let written: serde_yaml::Value = serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["current-context"].as_str(), Some("a"));
+        assert_eq!(written["future-top"].as_i64(), Some(1));
+        assert_eq!(written["preferences"]["future-preference"].as_bool(), Some(true));
+        assert_eq!(written["clusters"][0]["future-named-cluster"].as_str(), Some("x"));
+        assert_eq!(
+            written["clusters"][0]["cluster"]["disable-compression"].as_bool(),
+            Some(true)
+        );
+        assert_eq!(written["contexts"][0]["future-named-context"].as_str(), Some("x"));
+        assert_eq!(
+            written["contexts"][0]["context"]["future-context"].as_str(),
+            Some("x")
+        );
+        let user = &written["users"][0];
+        assert_eq!(user["future-named-user"].as_str(), Some("x"));
+        assert_eq!(user["user"]["future-user"].as_str(), Some("x"));
+        assert_eq!(user["user"]["exec"]["interactiveMode"].as_str(), Some("Never"));
+        // Fields that were not set are not added
+        assert!(user["user"]["exec"].get("provideClusterInfo").is_none());
+        assert_eq!(
+            written["users"][1]["user"]["auth-provider"]["future-provider"].as_str(),
+            Some("x")
+        );
+    }
+
+    #[test]
+    fn kubeconfig_write_fails_when_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        let lock = dir.path().join("config.lock");
+        fs::write(&lock, "").unwrap();
+        let err = Kubeconfig::default().write_to(&path).unwrap_err();
+        assert!(matches!(err, Error::Kubeconfig(ConfigError::LockFile { .. })));
+        assert!(lock.exists());
+
+        fs::remove_file(&lock).unwrap();
+        Kubeconfig::default().write_to(&path).unwrap();
+        assert!(path.exists());
+        assert!(!lock.exists());
+    }
 }
@@ -1,11 +1,12 @@
 use super::{
-    file_config::{AuthInfo, Cluster, Context, Kubeconfig},
+    file_config::{AuthInfo, Cluster, Context, ExecAuthCluster, Kubeconfig},
     utils,
 };
 use crate::{error::ConfigError, Result};
+use std::convert::TryFrom;
 
 /// KubeConfigOptions stores options used when loading kubeconfig file.
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Debug, PartialEq)]
 pub struct KubeConfigOptions {
     /// The named context to load
     pub context: Option<String>,
@@ -84,19 +85,27 @@ impl ConfigLoader {
             })?;
 
         let user_name = user.unwrap_or(&current_context.user);
-        let user = config
-            .auth_infos
-            .iter()
-            .find(|named_user| &named_user.name == user_name)
-            .map(|named_user| &named_user.auth_info)
-            .ok_or_else(|| ConfigError::FindUser {
-                user_name: user_name.clone(),
-            })?;
+        // Contexts without a user connect anonymously, like to a `kubectl proxy`
+        let mut user = if user_name.is_empty() {
+            AuthInfo::default()
+        } else {
+            config
+                .auth_infos
+                .iter()
+                .find(|named_user| &named_user.name == user_name)
+                .map(|named_user| named_user.auth_info.clone())
+                .ok_or_else(|| ConfigError::FindUser {
+                    user_name: user_name.clone(),
+                })?
+        };
+        if let Some(exec) = user.exec.as_mut().filter(|exec| exec.provide_cluster_info == Some(true)) {
+            exec.cluster = Some(ExecAuthCluster::try_from(cluster)?);
+        }
 
         Ok(ConfigLoader {
             current_context: current_context.clone(),
             cluster: cluster.clone(),
-            user: user.clone(),
+            user,
         })
     }
 
@@ -119,10 +128,8 @@ impl ConfigLoader {
     pub fn proxy_url(&self) -> Result<Option<http::Uri>> {
         let nonempty = |o: Option<String>| o.filter(|s| !s.is_empty());
 
-        if let Some(proxy) = nonempty(self.cluster.proxy_url.clone())
-            .or_else(|| nonempty(std::env::var("HTTP_PROXY").ok()))
-            .or_else(|| nonempty(std::env::var("HTTPS_PROXY").ok()))
-        {
+        // Environment variables are handled by the client since they depend on `NO_PROXY`
+        if let Some(proxy) = nonempty(self.cluster.proxy_url.clone()) {
             Ok(Some(proxy.parse::<http::Uri>()?))
         } else {
             Ok(None)
@@ -37,6 +37,23 @@ pub fn read_file<P: AsRef<Path>>(file: P) -> Result<Vec<u8>> {
     })
 }
 
+/// Hash of the contents of `file`, `None` when it can not be read.
+///
+/// Used to detect changes, since modification times can be too coarse to see quick successive writes,
+/// and are kept by tools that copy files into place.
+#[cfg(any(feature = "client", feature = "native-tls", feature = "rustls-tls"))]
+pub fn hash_file<P: AsRef<Path>>(file: P) -> Option<u64> {
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
+
+    let contents = fs::read(file).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
 pub fn read_file_to_string<P: AsRef<Path>>(file: P) -> Result<String> {
     fs::read_to_string(&file).map_err(|source| {
         ConfigError::ReadFile {
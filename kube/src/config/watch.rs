@@ -0,0 +1,262 @@
+//! Following changes to kubeconfig files
+use std::{path::PathBuf, time::Duration};
+
+use futures::{stream, Stream};
+
+use super::{file_config::Kubeconfig, file_loader::ConfigLoader, utils, Config, KubeConfigOptions};
+use crate::{error::ConfigError, Result};
+
+impl Config {
+    /// Watch the kubeconfig files for changes to the context, cluster or user that a [`Config`] is loaded from
+    ///
+    /// The stream yields a `Config` loaded like [`Config::from_kubeconfig`] first, and then a new one
+    /// whenever the kubeconfig files change what it would be loaded from, like after `kubectl config use-context`
+    /// or when credentials are rotated. Changes to other contexts are ignored.
+    ///
+    /// The files of `KUBECONFIG`, or `~/.kube/config`, are checked for modifications every `interval`,
+    /// along with the certificate, key and token files that the selected cluster and user refer to.
+    /// Failures to load them are yielded without ending the stream, and loading is tried again after the
+    /// next modification.
+    ///
+    /// ```no_run
+    /// # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+    /// use futures::StreamExt;
+    /// use kube::{config::KubeConfigOptions, Client, Config};
+    /// use std::{convert::TryFrom, time::Duration};
+    ///
+    /// let configs = Config::watch_kubeconfig(KubeConfigOptions::default(), Duration::from_secs(1))?;
+    /// futures::pin_mut!(configs);
+    /// while let Some(config) = configs.next().await {
+    ///     let client = Client::try_from(config?)?;
+    ///     // replace the client of the tool
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+    pub fn watch_kubeconfig(
+        options: KubeConfigOptions,
+        interval: Duration,
+    ) -> Result<impl Stream<Item = Result<Config>> + Send> {
+        let paths = match Kubeconfig::paths_from_env() {
+            Some(paths) => paths,
+            None => vec![utils::default_kube_path().ok_or(ConfigError::NoKubeconfigPath)?],
+        };
+        Ok(watch_paths(paths, options, interval))
+    }
+}
+
+// A hash of the contents of a file, `None` when it can not be read
+type Stamp = Option<u64>;
+
+struct WatchState {
+    paths: Vec<PathBuf>,
+    options: KubeConfigOptions,
+    interval: Duration,
+    started: bool,
+    // Files that the cluster and user of the last loaded `Config` refer to
+    referenced: Vec<PathBuf>,
+    stamps: Option<Vec<Stamp>>,
+    // The context, cluster and user of the last loaded `Config` serialized, and the stamps of their files
+    loaded: Option<(String, Vec<Stamp>)>,
+}
+
+fn watch_paths(
+    paths: Vec<PathBuf>,
+    options: KubeConfigOptions,
+    interval: Duration,
+) -> impl Stream<Item = Result<Config>> + Send {
+    let state = WatchState {
+        paths,
+        options,
+        interval,
+        started: false,
+        referenced: Vec::new(),
+        stamps: None,
+        loaded: None,
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if state.started {
+                tokio::time::sleep(state.interval).await;
+            }
+            state.started = true;
+
+            let files = state.paths.iter().chain(&state.referenced).cloned().collect();
+            let stamps = hash_files(files).await;
+            if state.stamps.as_ref() == Some(&stamps) {
+                continue;
+            }
+            state.stamps = Some(stamps);
+
+            let loader = match load(state.paths.clone(), &state.options).await {
+                Ok(loader) => loader,
+                Err(err) => return Some((Err(err), state)),
+            };
+            state.referenced = referenced_files(&loader);
+            let serialized =
+                match serde_json::to_string(&(&loader.current_context, &loader.cluster, &loader.user)) {
+                    Ok(serialized) => serialized,
+                    Err(err) => return Some((Err(ConfigError::SerializeKubeconfig(err).into()), state)),
+                };
+            let loaded = (serialized, hash_files(state.referenced.clone()).await);
+            if state.loaded.as_ref() == Some(&loaded) {
+                continue;
+            }
+            if state.loaded.is_some() {
+                tracing::info!("kubeconfig changed, reloading config");
+            }
+            state.loaded = Some(loaded);
+            return Some((Config::new_from_loader(loader).await, state));
+        }
+    })
+}
+
+// Reading and hashing files blocks, which must not stall the runtime
+async fn blocking<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+async fn hash_files(files: Vec<PathBuf>) -> Vec<Stamp> {
+    blocking(move || files.iter().map(utils::hash_file).collect()).await
+}
+
+async fn load(paths: Vec<PathBuf>, options: &KubeConfigOptions) -> Result<ConfigLoader> {
+    let kubeconfig = blocking(move || Kubeconfig::read_paths(&paths)).await?;
+    ConfigLoader::new_from_kubeconfig(kubeconfig, options).await
+}
+
+// The certificate, key and token files of the cluster and user
+fn referenced_files(loader: &ConfigLoader) -> Vec<PathBuf> {
+    [
+        &loader.cluster.certificate_authority,
+        &loader.user.client_certificate,
+        &loader.user.client_key,
+        &loader.user.token_file,
+    ]
+    .iter()
+    .filter_map(|path| path.as_ref().map(PathBuf::from))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use futures::{pin_mut, StreamExt};
+
+    use super::*;
+
+    fn kubeconfig(current_context: &str) -> String {
+        format!(
+            "apiVersion: v1
+kind: Config
+clusters:
+- name: a
+  cluster:
+    server: https://a:6443
+- name: b
+  cluster:
+    server: https://b:6443
+contexts:
+- name: a
+  context:
+    cluster: a
+    user: u
+- name: b
+  context:
+    cluster: b
+    user: u
+    namespace: ns
+users:
+- name: u
+  user:
+    token: t
+current-context: {}
+",
+            current_context
+        )
+    }
+
+    #[tokio::test]
+    async fn stamps_follow_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        assert_eq!(hash_files(vec![path.clone()]).await, vec![None]);
+        fs::write(&path, kubeconfig("a")).unwrap();
+        let first = hash_files(vec![path.clone()]).await;
+        assert!(first[0].is_some());
+        // Same length, different contents
+        fs::write(&path, kubeconfig("b")).unwrap();
+        assert_ne!(hash_files(vec![path.clone()]).await, first);
+        fs::write(&path, kubeconfig("a")).unwrap();
+        assert_eq!(hash_files(vec![path]).await, first);
+    }
+
+    #[tokio::test]
+    async fn yields_config_when_referenced_files_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, token) = (dir.path().join("config"), dir.path().join("token"));
+        fs::write(&token, "token-1").unwrap();
+        let config = kubeconfig("a").replace("token: t", &format!("tokenFile: {}", token.display()));
+        fs::write(&path, config).unwrap();
+        let configs = watch_paths(
+            vec![path],
+            KubeConfigOptions::default(),
+            Duration::from_millis(10),
+        );
+        pin_mut!(configs);
+
+        let config = configs.next().await.unwrap().unwrap();
+        assert_eq!(config.auth_info.token_file, Some(token.display().to_string()));
+        let next = tokio::time::timeout(Duration::from_millis(100), configs.next()).await;
+        assert!(next.is_err());
+
+        // Same size, different contents
+        fs::write(&token, "token-2").unwrap();
+        let next = tokio::time::timeout(Duration::from_secs(5), configs.next()).await;
+        assert!(next.unwrap().unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn yields_config_when_current_context_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        fs::write(&path, kubeconfig("a")).unwrap();
+        let configs = watch_paths(
+            vec![path.clone()],
+            KubeConfigOptions::default(),
+            Duration::from_millis(10),
+        );
+        pin_mut!(configs);
+
+        let config = configs.next().await.unwrap().unwrap();
+        assert_eq!(config.cluster_url, "https://a:6443/");
+
+        let mut config_file = Kubeconfig::read_from(&path).unwrap();
+        config_file.use_context("b").unwrap();
+        config_file.write().unwrap();
+        let config = configs.next().await.unwrap().unwrap();
+        assert_eq!(config.cluster_url, "https://b:6443/");
+        assert_eq!(config.default_namespace, "ns");
+
+        // Changes to other contexts do not yield a config
+        config_file.remove_context("a").unwrap();
+        config_file.write().unwrap();
+        let next = tokio::time::timeout(Duration::from_millis(200), configs.next()).await;
+        assert!(next.is_err());
+
+        fs::write(&path, "not a kubeconfig").unwrap();
+        assert!(configs.next().await.unwrap().is_err());
+        fs::write(&path, kubeconfig("a")).unwrap();
+        let config = configs.next().await.unwrap().unwrap();
+        assert_eq!(config.cluster_url, "https://a:6443/");
+    }
+}
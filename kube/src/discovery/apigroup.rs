@@ -6,6 +6,7 @@ use crate::{error::DiscoveryError, Client, Result};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{APIGroup, APIVersions};
 pub use kube_core::discovery::{verbs, ApiCapabilities, ApiResource, Scope};
 use kube_core::gvk::{GroupVersion, GroupVersionKind};
+use serde::{Deserialize, Serialize};
 
 
 /// Describes one API groups collected resources and capabilities.
@@ -61,6 +62,7 @@ use kube_core::gvk::{GroupVersion, GroupVersionKind};
 /// [`ApiGroup::versioned_resources`]: crate::discovery::ApiGroup::versioned_resources
 /// [`ApiGroup::recommended_resources`]: crate::discovery::ApiGroup::recommended_resources
 /// [`ApiGroup::recommended_kind`]: crate::discovery::ApiGroup::recommended_kind
+#[derive(Serialize, Deserialize)]
 pub struct ApiGroup {
     /// Name of the group e.g. apiregistration.k8s.io
     name: String,
@@ -0,0 +1,230 @@
+//! On-disk persistence of discovery results
+use super::{ApiGroup, DiscoveryMode};
+use crate::{error::DiscoveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+const CACHE_FILE: &str = "discovery.json";
+
+#[derive(Serialize, Deserialize)]
+struct CacheContents {
+    // The groups that were looked for, as the cache only holds those
+    mode: DiscoveryMode,
+    groups: Vec<CachedGroup>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedGroup {
+    fetched: SystemTime,
+    group: ApiGroup,
+}
+
+/// A directory holding discovery results that are considered fresh for `ttl`
+pub(crate) struct DiskCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl DiskCache {
+    pub(crate) fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(CACHE_FILE)
+    }
+
+    /// Load the cached groups and when they were queried, if the cache holds every group
+    /// queryable with `mode` and none of those has expired
+    ///
+    /// Unreadable or corrupt caches are treated as missing so that discovery falls back to the apiserver.
+    pub(crate) fn load(&self, mode: &DiscoveryMode) -> Option<HashMap<String, (ApiGroup, SystemTime)>> {
+        let path = self.path();
+        let contents: CacheContents = match fs::read(&path).map(|data| serde_json::from_slice(&data)) {
+            Ok(Ok(contents)) => contents,
+            Ok(Err(err)) => {
+                tracing::warn!("Ignoring corrupt discovery cache {:?}: {}", path, err);
+                return None;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                tracing::warn!("Ignoring unreadable discovery cache {:?}: {}", path, err);
+                return None;
+            }
+        };
+        if !contents.mode.covers(mode) {
+            tracing::debug!("Discovery cache {:?} does not hold all requested groups", path);
+            return None;
+        }
+        let now = SystemTime::now();
+        let expired = contents.groups.iter().any(|cached| {
+            let age = now.duration_since(cached.fetched).unwrap_or_default();
+            mode.is_queryable(&cached.group.name().to_string()) && age > self.ttl
+        });
+        if expired {
+            tracing::debug!("Discovery cache {:?} expired", path);
+            return None;
+        }
+        Some(
+            contents
+                .groups
+                .into_iter()
+                .map(|cached| (cached.group.name().to_string(), (cached.group, cached.fetched)))
+                .collect(),
+        )
+    }
+
+    /// Persist groups with when they were queried, replacing any previous cache atomically
+    pub(crate) fn store<'a>(
+        &self,
+        mode: &DiscoveryMode,
+        groups: impl Iterator<Item = (&'a ApiGroup, SystemTime)>,
+    ) -> Result<()> {
+        #[derive(Serialize)]
+        struct CacheContentsRef<'a> {
+            mode: &'a DiscoveryMode,
+            groups: Vec<CachedGroupRef<'a>>,
+        }
+        #[derive(Serialize)]
+        struct CachedGroupRef<'a> {
+            fetched: SystemTime,
+            group: &'a ApiGroup,
+        }
+        let data = serde_json::to_vec(&CacheContentsRef {
+            mode,
+            groups: groups
+                .map(|(group, fetched)| CachedGroupRef { fetched, group })
+                .collect(),
+        })?;
+        let path = self.path();
+        // A unique temporary file, as processes may share the cache directory
+        let write = || -> std::io::Result<()> {
+            fs::create_dir_all(&self.dir)?;
+            let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+            file.write_all(&data)?;
+            file.as_file().sync_all()?;
+            file.persist(&path).map_err(|err| err.error)?;
+            Ok(())
+        };
+        write().map_err(|source| DiscoveryError::WriteCache { path, source })?;
+        Ok(())
+    }
+
+    /// Remove the cache so that the next discovery run queries the apiserver
+    pub(crate) fn invalidate(&self) -> Result<()> {
+        let path = self.path();
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(DiscoveryError::RemoveCache { path, source: err }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The default cache directory for a cluster, following `kubectl`
+///
+/// This is `~/.kube/cache/discovery/<host>_<port>`, and `None` if the home directory cannot be determined.
+pub fn default_cache_dir(cluster_url: &http::Uri) -> Option<PathBuf> {
+    let host = cluster_url.host()?;
+    let port = cluster_url
+        .port_u16()
+        .unwrap_or_else(|| match cluster_url.scheme_str() {
+            Some("http") => 80,
+            _ => 443,
+        });
+    let dir = format!("{}_{}", host, port);
+    Some(
+        dirs::home_dir()?
+            .join(Path::new(".kube").join("cache").join("discovery"))
+            .join(dir),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_cache_dir_per_cluster() {
+        let dir = default_cache_dir(&"https://10.0.0.1:6443".parse().unwrap()).unwrap();
+        assert!(dir.ends_with(".kube/cache/discovery/10.0.0.1_6443"));
+        let dir = default_cache_dir(&"https://kubernetes.default.svc".parse().unwrap()).unwrap();
+        assert!(dir.ends_with(".kube/cache/discovery/kubernetes.default.svc_443"));
+    }
+
+    #[test]
+    fn cache_roundtrip_and_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let group: ApiGroup = serde_json::from_value(serde_json::json!({
+            "name": "clux.dev",
+            "data": [{
+                "version": "v1",
+                "resources": [[
+                    { "group": "clux.dev", "version": "v1", "api_version": "clux.dev/v1", "kind": "Foo", "plural": "foos" },
+                    { "scope": "Namespaced", "subresources": [], "operations": ["get", "list"] }
+                ]]
+            }],
+            "preferred": "v1"
+        }))
+        .unwrap();
+
+        let all = DiscoveryMode::Block(vec![]);
+        let cache = DiskCache::new(dir.path().to_path_buf(), Duration::from_secs(60));
+        assert!(cache.load(&all).is_none());
+        cache
+            .store(&all, std::iter::once((&group, SystemTime::now())))
+            .unwrap();
+        let loaded = cache.load(&all).unwrap();
+        let (ar, caps) = loaded["clux.dev"].0.recommended_kind("Foo").unwrap();
+        assert_eq!(ar.plural, "foos");
+        assert!(caps.supports_operation("list"));
+
+        let expired = DiskCache::new(dir.path().to_path_buf(), Duration::from_secs(0));
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(expired.load(&all).is_none());
+
+        cache.invalidate().unwrap();
+        assert!(cache.load(&all).is_none());
+        cache.invalidate().unwrap();
+    }
+
+    #[test]
+    fn cache_is_only_reused_for_covered_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let group = |name: &str| -> ApiGroup {
+            serde_json::from_value(serde_json::json!({ "name": name, "data": [], "preferred": null }))
+                .unwrap()
+        };
+        let (foo, bar) = (group("foo.dev"), group("bar.dev"));
+        let all = DiscoveryMode::Block(vec![]);
+        let only_foo = DiscoveryMode::Allow(vec!["foo.dev".into()]);
+        let cache = DiskCache::new(dir.path().to_path_buf(), Duration::from_secs(60));
+
+        // A filtered discovery cannot answer an unfiltered one
+        cache
+            .store(&only_foo, std::iter::once((&foo, SystemTime::now())))
+            .unwrap();
+        assert!(cache.load(&all).is_none());
+        assert!(cache.load(&only_foo).is_some());
+        assert!(cache
+            .load(&DiscoveryMode::Block(vec!["bar.dev".into()]))
+            .is_none());
+
+        // Groups expire on their own
+        let stale = SystemTime::now() - Duration::from_secs(120);
+        let groups = vec![(&foo, SystemTime::now()), (&bar, stale)];
+        cache.store(&all, groups.into_iter()).unwrap();
+        assert!(cache.load(&all).is_none());
+        assert!(cache.load(&only_foo).is_some());
+        assert!(cache
+            .load(&DiscoveryMode::Block(vec!["bar.dev".into()]))
+            .is_some());
+    }
+}
@@ -3,8 +3,16 @@
 use crate::{Client, Result};
 pub use kube_core::discovery::{verbs, ApiCapabilities, ApiResource, Scope};
 use kube_core::gvk::GroupVersionKind;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 mod apigroup;
+mod cache;
+pub use cache::default_cache_dir;
+use cache::DiskCache;
 pub mod oneshot;
 pub use apigroup::ApiGroup;
 mod parse;
@@ -15,6 +23,7 @@ mod version;
 pub use oneshot::{group, pinned_group, pinned_kind};
 
 /// How the Discovery client decides what api groups to scan
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum DiscoveryMode {
     /// Only allow explicitly listed apigroups
     Allow(Vec<String>),
@@ -30,6 +39,17 @@ impl DiscoveryMode {
             Self::Block(blocked) => !blocked.contains(group),
         }
     }
+
+    /// Whether every group that is queryable with `other` is also queryable with `self`
+    fn covers(&self, other: &DiscoveryMode) -> bool {
+        match (self, other) {
+            (_, Self::Allow(allowed)) => allowed.iter().all(|g| self.is_queryable(g)),
+            (Self::Block(blocked), Self::Block(other_blocked)) => {
+                blocked.iter().all(|g| other_blocked.contains(g))
+            }
+            (Self::Allow(_), Self::Block(_)) => false,
+        }
+    }
 }
 
 /// A caching client for running API discovery against the Kubernetes API.
@@ -50,12 +70,19 @@ impl DiscoveryMode {
 ///
 /// If caching of results is __not required__, then a simpler [`oneshot`](crate::discovery::oneshot) discovery system can be used.
 ///
+/// Results can also be persisted to disk between runs with [`Discovery::cache_dir`].
+///
 /// [`ApiGroup`]: crate::discovery::ApiGroup
 #[cfg_attr(docsrs, doc(cfg(feature = "client")))]
 pub struct Discovery {
     client: Client,
     groups: HashMap<String, ApiGroup>,
+    // When each group was queried from the apiserver
+    fetched: HashMap<String, SystemTime>,
     mode: DiscoveryMode,
+    cache: Option<DiskCache>,
+    // Whether `run` completed, so that the groups can be persisted as a full discovery
+    populated: bool,
 }
 
 /// Caching discovery interface
@@ -66,7 +93,14 @@ impl Discovery {
     pub fn new(client: Client) -> Self {
         let groups = HashMap::new();
         let mode = DiscoveryMode::Block(vec![]);
-        Self { client, groups, mode }
+        Self {
+            client,
+            groups,
+            fetched: HashMap::new(),
+            mode,
+            cache: None,
+            populated: false,
+        }
     }
 
     /// Configure the discovery client to only look for the listed apigroups
@@ -81,11 +115,43 @@ impl Discovery {
         self
     }
 
+    /// Persist discovery results in `dir` and reuse them for `ttl` instead of querying the apiserver
+    ///
+    /// This works like the `kubectl` discovery cache; use [`default_cache_dir`] for its location.
+    /// The directory should be unique per cluster. The cache is written by [`Discovery::run`] and
+    /// kept up to date by [`Discovery::refresh_group`]. Each group expires `ttl` after it was queried.
+    ///
+    /// The cache records the [`filter`](Discovery::filter) or [`exclude`](Discovery::exclude) it was
+    /// written with, and is only reused by discoveries looking for a subset of its groups.
+    ///
+    /// ```no_run
+    /// use kube::{Client, Config, discovery::{self, Discovery}};
+    /// use std::{convert::TryFrom, time::Duration};
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), kube::Error> {
+    ///     let config = Config::infer().await?;
+    ///     let dir = discovery::default_cache_dir(&config.cluster_url).unwrap();
+    ///     let client = Client::try_from(config)?;
+    ///     let discovery = Discovery::new(client)
+    ///         .cache_dir(dir, Duration::from_secs(600))
+    ///         .run()
+    ///         .await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        self.cache = Some(DiskCache::new(dir.into(), ttl));
+        self
+    }
+
     /// Runs or re-runs the configured discovery algorithm and updates/populates the cache
     ///
     /// The cache is empty cleared when this is started. By default, every api group found is checked,
     /// causing `N+2` queries to the api server (where `N` is number of api groups).
     ///
+    /// If a [`cache_dir`](Discovery::cache_dir) is configured and has not expired, the groups are
+    /// loaded from disk instead, and no queries are made.
+    ///
     /// ```no_run
     /// use kube::{Client, api::{Api, DynamicObject}, discovery::{Discovery, verbs, Scope}, ResourceExt};
     /// #[tokio::main]
@@ -110,12 +176,24 @@ impl Discovery {
     /// See a bigger example in [examples/dynamic.api](https://github.com/clux/kube-rs/blob/master/examples/dynamic_api.rs)
     pub async fn run(mut self) -> Result<Self> {
         self.groups.clear();
+        self.fetched.clear();
+        if let Some(cached) = self.cache.as_ref().and_then(|cache| cache.load(&self.mode)) {
+            for (key, (apigroup, fetched)) in cached {
+                if self.mode.is_queryable(&key) {
+                    self.fetched.insert(key.clone(), fetched);
+                    self.groups.insert(key, apigroup);
+                }
+            }
+            self.populated = true;
+            return Ok(self);
+        }
         let api_groups = self.client.list_api_groups().await?;
         // query regular groups + crds under /apis
         for g in api_groups.groups {
             let key = g.name.clone();
             if self.mode.is_queryable(&key) {
                 let apigroup = ApiGroup::query_apis(&self.client, g).await?;
+                self.fetched.insert(key.clone(), SystemTime::now());
                 self.groups.insert(key, apigroup);
             }
         }
@@ -124,10 +202,64 @@ impl Discovery {
         if self.mode.is_queryable(&corekey) {
             let coreapis = self.client.list_core_api_versions().await?;
             let apigroup = ApiGroup::query_core(&self.client, coreapis).await?;
+            self.fetched.insert(corekey.clone(), SystemTime::now());
             self.groups.insert(corekey, apigroup);
         }
+        self.populated = true;
+        self.store_cache()?;
         Ok(self)
     }
+
+    /// Re-queries a single api group and updates its entry in the cache
+    ///
+    /// This is useful after installing or changing a `CustomResourceDefinition`, as it avoids
+    /// re-running the full discovery. The group is removed from the cache if it is no longer served.
+    pub async fn refresh_group(&mut self, group: &str) -> Result<()> {
+        if !self.mode.is_queryable(&group.to_string()) {
+            return Ok(());
+        }
+        if group == ApiGroup::CORE_GROUP {
+            let coreapis = self.client.list_core_api_versions().await?;
+            let apigroup = ApiGroup::query_core(&self.client, coreapis).await?;
+            self.fetched.insert(group.to_string(), SystemTime::now());
+            self.groups.insert(group.to_string(), apigroup);
+            return self.store_cache();
+        }
+        let api_groups = self.client.list_api_groups().await?;
+        match api_groups.groups.into_iter().find(|g| g.name == group) {
+            Some(g) => {
+                let apigroup = ApiGroup::query_apis(&self.client, g).await?;
+                self.fetched.insert(group.to_string(), SystemTime::now());
+                self.groups.insert(group.to_string(), apigroup);
+            }
+            None => {
+                self.fetched.remove(group);
+                self.groups.remove(group);
+            }
+        }
+        self.store_cache()
+    }
+
+    /// Removes the on-disk cache, forcing the next [`Discovery::run`] to query the apiserver
+    ///
+    /// This does nothing when no [`cache_dir`](Discovery::cache_dir) is configured.
+    pub fn invalidate_cache(&self) -> Result<()> {
+        match &self.cache {
+            Some(cache) => cache.invalidate(),
+            None => Ok(()),
+        }
+    }
+
+    // Only a completed `run` is persisted, as the cache is read back as the full discovery of its mode
+    fn store_cache(&self) -> Result<()> {
+        match &self.cache {
+            Some(cache) if self.populated => {
+                let fetched = |key: &String| self.fetched.get(key).copied().unwrap_or_else(SystemTime::now);
+                cache.store(&self.mode, self.groups.iter().map(|(k, g)| (g, fetched(k))))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 /// Interface to the Discovery cache
@@ -151,10 +283,100 @@ impl Discovery {
     ///
     /// This is for quick extraction after having done a complete discovery.
     /// If you are only interested in a single kind, consider [`oneshot::pinned_kind`](crate::discovery::pinned_kind).
-    pub fn resolve_gvk(&self, gvk: &GroupVersionKind) -> Option<(ApiResource, ApiCapabilities)> {
+    ///
+    /// With a [`cache_dir`](Discovery::cache_dir), the groups may have been loaded from a cache written
+    /// before the kind was installed. A miss then re-queries the group with [`Discovery::refresh_group`].
+    pub async fn resolve_gvk(
+        &mut self,
+        gvk: &GroupVersionKind,
+    ) -> Result<Option<(ApiResource, ApiCapabilities)>> {
+        if let Some(found) = self.find_gvk(gvk) {
+            return Ok(Some(found));
+        }
+        if self.cache.is_none() {
+            return Ok(None);
+        }
+        self.refresh_group(&gvk.group).await?;
+        Ok(self.find_gvk(gvk))
+    }
+
+    fn find_gvk(&self, gvk: &GroupVersionKind) -> Option<(ApiResource, ApiCapabilities)> {
         self.get(&gvk.group)?
             .versioned_resources(&gvk.version)
             .into_iter()
             .find(|res| res.0.kind == gvk.kind)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Request, Response};
+    use hyper::Body;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[tokio::test]
+    async fn resolve_gvk_refreshes_cached_groups_on_a_miss() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let service = tower::service_fn(move |req: Request<Body>| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let body = match req.uri().path() {
+                "/apis" => serde_json::json!({
+                    "groups": [{
+                        "name": "clux.dev",
+                        "versions": [{ "groupVersion": "clux.dev/v1", "version": "v1" }],
+                        "preferredVersion": { "groupVersion": "clux.dev/v1", "version": "v1" }
+                    }]
+                }),
+                "/apis/clux.dev/v1" => serde_json::json!({
+                    "groupVersion": "clux.dev/v1",
+                    "resources": [{
+                        "name": "foos",
+                        "singularName": "foo",
+                        "namespaced": true,
+                        "kind": "Foo",
+                        "verbs": ["get", "list"]
+                    }]
+                }),
+                path => panic!("unexpected request to {}", path),
+            };
+            async move { Response::builder().body(Body::from(body.to_string())) }
+        });
+        let client = Client::new(service, "default");
+        let gvk = GroupVersionKind::gvk("clux.dev", "v1", "Foo");
+
+        // Without a cache, the discovery is taken as complete
+        let mut discovery = Discovery::new(client.clone());
+        assert!(discovery.resolve_gvk(&gvk).await.unwrap().is_none());
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+        // A cache written before the crd was installed
+        let dir = tempfile::tempdir().unwrap();
+        let ttl = Duration::from_secs(60);
+        DiskCache::new(dir.path().to_path_buf(), ttl)
+            .store(&DiscoveryMode::Block(vec![]), std::iter::empty())
+            .unwrap();
+        let mut discovery = Discovery::new(client)
+            .cache_dir(dir.path(), ttl)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+        let (ar, caps) = discovery.resolve_gvk(&gvk).await.unwrap().unwrap();
+        assert_eq!(ar.plural, "foos");
+        assert!(caps.supports_operation(verbs::LIST));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        // The refreshed group is found without further requests, and was persisted
+        assert!(discovery.resolve_gvk(&gvk).await.unwrap().is_some());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        let cached = DiskCache::new(dir.path().to_path_buf(), ttl)
+            .load(&DiscoveryMode::Block(vec![]))
+            .unwrap();
+        assert!(cached.contains_key("clux.dev"));
+    }
+}
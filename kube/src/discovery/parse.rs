@@ -5,6 +5,7 @@ use kube_core::{
     discovery::{ApiCapabilities, ApiResource, Scope},
     gvk::GroupVersion,
 };
+use serde::{Deserialize, Serialize};
 
 /// Creates an `ApiResource` from a `meta::v1::APIResource` instance + its groupversion.
 ///
@@ -54,6 +55,7 @@ pub(crate) fn parse_apicapabilities(list: &APIResourceList, name: &str) -> Resul
 }
 
 /// Internal resource information and capabilities for a particular ApiGroup at a particular version
+#[derive(Serialize, Deserialize)]
 pub(crate) struct GroupVersionData {
     /// Pinned api version
     pub(crate) version: String,
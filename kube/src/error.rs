@@ -80,6 +80,13 @@ pub enum Error {
     #[error("Error from discovery: {0}")]
     Discovery(#[from] DiscoveryError),
 
+    /// The apiserver returned warnings while warnings are treated as errors
+    ///
+    /// See [`Client::warnings_as_errors`](crate::Client::warnings_as_errors).
+    #[cfg(feature = "client")]
+    #[error("Request returned warnings: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Warnings(Vec<crate::client::Warning>),
+
     /// An error with configuring SSL occured
     #[error("SslError: {0}")]
     SslError(String),
@@ -135,6 +142,9 @@ pub enum ConfigError {
     #[error("Invalid bearer token: {0}")]
     InvalidBearerToken(#[source] InvalidHeaderValue),
 
+    #[error("Invalid impersonation: {0}")]
+    InvalidImpersonation(#[source] InvalidHeaderValue),
+
     #[error("Tried to refresh a token and got a non-refreshable token response")]
     /// Tried to refresh a token and got a non-refreshable token response
     UnrefreshableTokenResponse,
@@ -181,6 +191,11 @@ pub enum ConfigError {
     #[error("OAuth Error: {0}")]
     OAuth(#[from] OAuthError),
 
+    #[cfg(feature = "client")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+    #[error("OIDC Error: {0}")]
+    Oidc(#[from] OidcError),
+
     #[error("Unable to load config file: {0}")]
     LoadConfigFile(#[source] Box<Error>),
     #[error("Unable to load current context: {context_name}")]
@@ -190,6 +205,11 @@ pub enum ConfigError {
     #[error("Unable to find named user: {user_name}")]
     FindUser { user_name: String },
 
+    #[error("Unable to find cluster in registry: {cluster_name}")]
+    UnknownCluster { cluster_name: String },
+    #[error("Secret {name} has no kubeconfig under key {key}")]
+    KubeconfigSecret { name: String, key: String },
+
     #[error("Unable to find path of kubeconfig")]
     NoKubeconfigPath,
 
@@ -203,6 +223,18 @@ pub enum ConfigError {
         #[source]
         source: std::io::Error,
     },
+    #[error("Failed to lock '{path:?}': {source}")]
+    LockFile {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to write '{path:?}': {source}")]
+    WriteFile {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
     #[error("Failed to get data/file with base64 format")]
     NoBase64FileOrData,
     #[error("Failed to get data/file")]
@@ -211,6 +243,11 @@ pub enum ConfigError {
     #[error("Failed to parse Kubeconfig YAML: {0}")]
     ParseYaml(#[source] serde_yaml::Error),
 
+    #[error("Failed to serialize Kubeconfig: {0}")]
+    SerializeKubeconfig(#[source] serde_json::Error),
+    #[error("Failed to serialize Kubeconfig YAML: {0}")]
+    SerializeYaml(#[source] serde_yaml::Error),
+
     #[error("Failed to find a single YAML document in Kubeconfig: {0}")]
     EmptyKubeconfig(PathBuf),
 
@@ -224,8 +261,13 @@ pub enum ConfigError {
     },
     #[error("Failed to parse auth exec output: {0}")]
     AuthExecParse(#[source] serde_json::Error),
+    #[error("Failed to serialize KUBERNETES_EXEC_INFO for auth exec: {0}")]
+    AuthExecSerialize(#[source] serde_json::Error),
     #[error("Failed exec auth: {0}")]
     AuthExec(String),
+
+    #[error("Unsupported proxy url: {0}")]
+    UnsupportedProxyUrl(String),
 }
 
 #[cfg(feature = "oauth")]
@@ -264,6 +306,37 @@ impl From<OAuthError> for Error {
     }
 }
 
+#[cfg(feature = "client")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+#[derive(Error, Debug)]
+// Redundant with the error messages and machine names
+#[allow(missing_docs)]
+/// Possible errors when refreshing an OIDC id-token
+pub enum OidcError {
+    #[error("No id-token for oidc authentication provider, and no refresh-token to get one")]
+    MissingIdToken,
+    #[error("The oidc id-token has expired, and refresh-token, idp-issuer-url or client-id is missing")]
+    MissingRefreshConfig,
+    #[error("Invalid oidc id-token: {0}")]
+    InvalidIdToken(String),
+    #[error("Unable to request oidc token: {0}")]
+    Request(#[source] hyper::Error),
+    #[error("Oidc request to {url} failed with status {status}")]
+    HttpStatus { url: String, status: http::StatusCode },
+    #[error("Unable to parse oidc response: {0}")]
+    ParseResponse(#[source] serde_json::Error),
+    #[error("Oidc token response did not contain an id_token")]
+    NoIdTokenInResponse,
+}
+
+#[cfg(feature = "client")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+impl From<OidcError> for Error {
+    fn from(e: OidcError) -> Self {
+        ConfigError::Oidc(e).into()
+    }
+}
+
 #[derive(Error, Debug)]
 // Redundant with the error messages and machine names
 #[allow(missing_docs)]
@@ -279,6 +352,18 @@ pub enum DiscoveryError {
     MissingResource(String),
     #[error("Empty Api Group: {0}")]
     EmptyApiGroup(String),
+    #[error("Failed to write discovery cache '{path:?}': {source}")]
+    WriteCache {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to remove discovery cache '{path:?}': {source}")]
+    RemoveCache {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
 }
 
 impl From<kube_core::Error> for Error {
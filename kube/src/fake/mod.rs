@@ -0,0 +1,752 @@
+//! An in-memory apiserver for testing code built on [`Client`] without a cluster.
+//!
+//! [`FakeApiServer`] is a `Service` that is used with [`Client::new`], and serves any resource
+//! under `/api` and `/apis` without prior registration. It implements
+//!
+//! - `resourceVersion`s that increase with every write, and `409 Conflict` on stale updates
+//! - lists and watches filtered by label and field selectors
+//! - watches from a `resourceVersion`, with bookmarks and `410 Gone` for versions older than the retained history
+//! - JSON, merge and apply patches
+//! - finalizers, with `deletionTimestamp` set on deletion of objects that have any
+//! - the `status` subresource, which every resource is assumed to have
+//! - `dryRun=All`
+//!
+//! Strategic merge patches and server-side apply are both treated as JSON merge patches,
+//! so lists are replaced instead of merged and field managers are not tracked.
+//! Objects are stored as they are written, without validation, defaulting or conversion between versions.
+//!
+//! To test against the responses of a real apiserver instead, record them with [`RecordLayer`]
+//! and serve them back with [`Replay`].
+//!
+//! ```
+//! # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+//! use k8s_openapi::api::core::v1::ConfigMap;
+//! use kube::{api::{Api, PostParams}, fake::FakeApiServer};
+//!
+//! let server = FakeApiServer::new();
+//! let cms: Api<ConfigMap> = Api::default_namespaced(server.client());
+//! let cm = serde_json::from_value(serde_json::json!({
+//!     "apiVersion": "v1",
+//!     "kind": "ConfigMap",
+//!     "metadata": { "name": "settings" },
+//! }))?;
+//! let created = cms.create(&PostParams::default(), &cm).await?;
+//! assert_eq!(created.metadata.resource_version.as_deref(), Some("1"));
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use http::{header::CONTENT_TYPE, request::Parts, Method, Request, Response, StatusCode};
+use hyper::Body;
+use json_patch::PatchOperation;
+use serde_json::{json, Value};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::{timeout_at, Instant},
+};
+use tower::Service;
+
+use crate::Client;
+
+mod record;
+mod selector;
+mod store;
+pub use record::{Interaction, Record, RecordBody, RecordLayer, RecordedRequest, RecordedResponse, Replay};
+use selector::{FieldSelector, LabelSelector};
+use store::{Change, Failure, Notification, Part, Resource, Selector, Store};
+
+/// An in-memory apiserver to use with [`Client::new`].
+///
+/// Clones share the same objects. See the [module documentation](self) for what is supported.
+#[derive(Clone)]
+pub struct FakeApiServer {
+    store: Arc<Mutex<Store>>,
+}
+
+impl Default for FakeApiServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeApiServer {
+    /// Create an empty apiserver that keeps the last 1000 changes for watches.
+    pub fn new() -> Self {
+        Self::with_history_limit(1000)
+    }
+
+    /// Create an empty apiserver that keeps the last `limit` changes for watches.
+    ///
+    /// Watches from a `resourceVersion` older than the retained changes fail with `410 Gone`.
+    pub fn with_history_limit(limit: usize) -> Self {
+        Self {
+            store: Arc::new(Mutex::new(Store::new(limit))),
+        }
+    }
+
+    /// Create a [`Client`] for this apiserver, with `default` as the default namespace.
+    pub fn client(&self) -> Client {
+        Client::new(self.clone(), "default")
+    }
+
+    /// Send a bookmark with the current `resourceVersion` to the watches that allow bookmarks.
+    pub fn bookmark(&self) {
+        self.store().bookmark();
+    }
+
+    /// Forget the history of changes, so that watches from any earlier `resourceVersion` fail with `410 Gone`.
+    pub fn compact(&self) {
+        self.store().compact();
+    }
+
+    fn store(&self) -> MutexGuard<'_, Store> {
+        self.store.lock().expect("fake apiserver lock poisoned")
+    }
+
+    fn handle(&self, parts: &Parts, body: &[u8]) -> store::Result<Response<Body>> {
+        let target = Target::parse(parts.uri.path()).ok_or_else(|| {
+            Failure::new(
+                StatusCode::NOT_FOUND,
+                "NotFound",
+                format!(
+                    "the server could not find the requested resource ({})",
+                    parts.uri.path()
+                ),
+            )
+        })?;
+        let query = Query::parse(parts.uri.query().unwrap_or_default());
+        let part = match target.subresource.as_deref() {
+            None => Part::Main,
+            Some("status") => Part::Status,
+            Some(other) => {
+                return Err(Failure::new(
+                    StatusCode::NOT_FOUND,
+                    "NotFound",
+                    format!("subresource {:?} is not supported", other),
+                ))
+            }
+        };
+        let (resource, namespace) = (&target.resource, target.namespace.as_deref().unwrap_or_default());
+        let dry_run = query.get("dryRun") == Some("All");
+
+        let obj = match (&parts.method, target.name.as_deref()) {
+            (&Method::GET, None) if matches!(query.get("watch"), Some("true") | Some("1")) => {
+                return self.watch(&target, &query)
+            }
+            (&Method::GET, None) => self.list(&target, &query)?,
+            (&Method::GET, Some(name)) => self.store().get(resource, namespace, name)?,
+            (&Method::POST, None) => self.store().create(resource, namespace, parse(body)?, dry_run)?,
+            (&Method::PUT, Some(name)) => {
+                self.store()
+                    .update(resource, namespace, name, part, parse(body)?, dry_run)?
+            }
+            (&Method::PATCH, Some(name)) => {
+                let content_type = parts.headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+                self.patch(
+                    &target,
+                    name,
+                    part,
+                    content_type.unwrap_or_default(),
+                    body,
+                    dry_run,
+                )?
+            }
+            (&Method::DELETE, Some(name)) => {
+                let options = if body.is_empty() { json!({}) } else { parse(body)? };
+                let dry_run = dry_run || options["dryRun"] == json!(["All"]);
+                self.store()
+                    .delete(resource, namespace, name, &options["preconditions"], dry_run)?
+            }
+            (&Method::DELETE, None) => {
+                let options = if body.is_empty() { json!({}) } else { parse(body)? };
+                let dry_run = dry_run || options["dryRun"] == json!(["All"]);
+                let selector = selector(&target, &query)?;
+                let mut store = self.store();
+                let items = store
+                    .list(resource, &selector)
+                    .into_iter()
+                    .map(|obj| {
+                        let (ns, name) = namespaced_name(&obj);
+                        store.delete(resource, &ns, &name, &options["preconditions"], dry_run)
+                    })
+                    .collect::<store::Result<Vec<_>>>()?;
+                list_response(&store, resource, items)
+            }
+            _ => {
+                return Err(Failure::new(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "MethodNotAllowed",
+                    "the server does not allow this method on the requested resource",
+                ))
+            }
+        };
+        let status = if parts.method == Method::POST {
+            StatusCode::CREATED
+        } else {
+            StatusCode::OK
+        };
+        Ok(json_response(status, &obj))
+    }
+
+    fn list(&self, target: &Target, query: &Query) -> store::Result<Value> {
+        let selector = selector(target, query)?;
+        let store = self.store();
+        let items = store.list(&target.resource, &selector);
+        Ok(list_response(&store, &target.resource, items))
+    }
+
+    fn patch(
+        &self,
+        target: &Target,
+        name: &str,
+        part: Part,
+        content_type: &str,
+        body: &[u8],
+        dry_run: bool,
+    ) -> store::Result<Value> {
+        let (resource, namespace) = (&target.resource, target.namespace.as_deref().unwrap_or_default());
+        let patch: Value = parse(body)?;
+        let mut store = self.store();
+        let mut obj = match store.get(resource, namespace, name) {
+            Ok(obj) => obj,
+            // Apply creates missing objects
+            Err(_) if content_type == "application/apply-patch+yaml" && part == Part::Main => {
+                return store.create(resource, namespace, patch, dry_run);
+            }
+            Err(err) => return Err(err),
+        };
+        match content_type {
+            "application/json-patch+json" => {
+                let patch: json_patch::Patch =
+                    serde_json::from_value(patch).map_err(|e| Failure::bad_request(e.to_string()))?;
+                for op in patch.0 {
+                    // Like the apiserver, testing that a missing value is null succeeds
+                    if let PatchOperation::Test(test) = &op {
+                        if test.value.is_null() && obj.pointer(&test.path).is_none() {
+                            continue;
+                        }
+                    }
+                    json_patch::patch(&mut obj, &json_patch::Patch(vec![op])).map_err(|e| {
+                        Failure::new(StatusCode::UNPROCESSABLE_ENTITY, "Invalid", e.to_string())
+                    })?;
+                }
+            }
+            "application/merge-patch+json"
+            | "application/strategic-merge-patch+json"
+            | "application/apply-patch+yaml" => json_patch::merge(&mut obj, &patch),
+            other => {
+                return Err(Failure::new(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "UnsupportedMediaType",
+                    format!("the body of the request was in an unknown format: {}", other),
+                ))
+            }
+        }
+        store.update(resource, namespace, name, part, obj, dry_run)
+    }
+
+    fn watch(&self, target: &Target, query: &Query) -> store::Result<Response<Body>> {
+        let selector = selector(target, query)?;
+        let bookmarks = query.get("allowWatchBookmarks") == Some("true");
+        let timeout = query
+            .get("timeoutSeconds")
+            .and_then(|t| t.parse().ok())
+            .map(Duration::from_secs);
+        let resource = target.resource.clone();
+
+        // Subscribe while holding the lock so that no changes are missed between the history and the subscription
+        let store = self.store();
+        let notifications = store.subscribe();
+        let initial = match query.get("resourceVersion").unwrap_or_default() {
+            // Start with the current objects
+            "" | "0" => store
+                .list(&resource, &selector)
+                .into_iter()
+                .map(|obj| watch_event("ADDED", obj))
+                .collect(),
+            rv => {
+                let rv = rv
+                    .parse()
+                    .map_err(|_| Failure::bad_request(format!("invalid resourceVersion: {}", rv)))?;
+                match store.changes_since(rv) {
+                    Ok(changes) => changes
+                        .filter_map(|change| change_event(&resource, &selector, change))
+                        .collect(),
+                    Err(gone) => vec![watch_event("ERROR", gone.to_status())],
+                }
+            }
+        };
+        let expired = initial.iter().any(|event| event["type"] == "ERROR");
+        let api_version = resource.api_version();
+        let kind = store.kind(&resource).unwrap_or_default().to_owned();
+        drop(store);
+
+        let live = Watch {
+            notifications,
+            resource,
+            selector,
+            bookmark: bookmarks.then(|| json!({ "apiVersion": api_version, "kind": kind })),
+            deadline: timeout.map(|t| Instant::now() + t),
+        };
+        let live = if expired { None } else { Some(live) };
+        let events = stream::iter(initial)
+            .chain(stream::unfold(live, |watch| async move { watch?.next().await }))
+            .map(|event| {
+                let mut line = serde_json::to_vec(&event).expect("serializing JSON");
+                line.push(b'\n');
+                Ok::<_, Infallible>(Bytes::from(line))
+            });
+        Ok(Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::wrap_stream(events))
+            .expect("valid response"))
+    }
+}
+
+// Changes for a running watch
+struct Watch {
+    notifications: broadcast::Receiver<Notification>,
+    resource: Resource,
+    selector: Selector,
+    // Type of the bookmarks to send, if allowed
+    bookmark: Option<Value>,
+    deadline: Option<Instant>,
+}
+
+impl Watch {
+    // Returns the next event, and `self` unless the watch ended with it
+    async fn next(mut self) -> Option<(Value, Option<Self>)> {
+        loop {
+            let notification = match self.deadline {
+                Some(deadline) => timeout_at(deadline, self.notifications.recv()).await.ok()?,
+                None => self.notifications.recv().await,
+            };
+            match notification {
+                Ok(Notification::Change(change)) => {
+                    if let Some(event) = change_event(&self.resource, &self.selector, &change) {
+                        return Some((event, Some(self)));
+                    }
+                }
+                Ok(Notification::Bookmark(rv)) => {
+                    if let Some(mut bookmark) = self.bookmark.clone() {
+                        bookmark["metadata"] = json!({ "resourceVersion": rv.to_string() });
+                        return Some((watch_event("BOOKMARK", bookmark), Some(self)));
+                    }
+                }
+                Err(RecvError::Lagged(_)) => {
+                    let gone = Failure::gone("watch fell behind the changes");
+                    return Some((watch_event("ERROR", gone.to_status()), None));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Service<Request<Body>> for FakeApiServer {
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let server = self.clone();
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let res = match hyper::body::to_bytes(body).await {
+                Ok(body) => server.handle(&parts, &body),
+                Err(err) => Err(Failure::bad_request(err.to_string())),
+            };
+            Ok(res.unwrap_or_else(|failure| json_response(failure.code, &failure.to_status())))
+        })
+    }
+}
+
+/// The resource and object a request is for.
+#[derive(Debug, PartialEq)]
+struct Target {
+    resource: Resource,
+    namespace: Option<String>,
+    name: Option<String>,
+    subresource: Option<String>,
+}
+
+impl Target {
+    // Parses `/api/{version}/...` and `/apis/{group}/{version}/...`
+    fn parse(path: &str) -> Option<Self> {
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        let (group, version, mut rest) = match segments.as_slice() {
+            ["api", version, rest @ ..] => ("", *version, rest),
+            ["apis", group, version, rest @ ..] => (*group, *version, rest),
+            _ => return None,
+        };
+        let mut namespace = None;
+        // `namespaces/{name}/status` is the status of a namespace rather than resources in it
+        if rest.len() >= 3 && rest[0] == "namespaces" && !(rest.len() == 3 && rest[2] == "status") {
+            namespace = Some(rest[1].to_owned());
+            rest = &rest[2..];
+        }
+        let (plural, name, subresource) = match rest {
+            [plural] => (plural, None, None),
+            [plural, name] => (plural, Some(name), None),
+            [plural, name, subresource] => (plural, Some(name), Some(subresource)),
+            _ => return None,
+        };
+        Some(Self {
+            resource: Resource {
+                group: group.into(),
+                version: version.into(),
+                plural: (*plural).into(),
+            },
+            namespace,
+            name: name.map(|n| (*n).to_owned()),
+            subresource: subresource.map(|s| (*s).to_owned()),
+        })
+    }
+}
+
+struct Query(Vec<(String, String)>);
+
+impl Query {
+    fn parse(query: &str) -> Self {
+        Self(form_urlencoded::parse(query.as_bytes()).into_owned().collect())
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+fn selector(target: &Target, query: &Query) -> store::Result<Selector> {
+    Ok(Selector {
+        namespace: target.namespace.clone(),
+        labels: LabelSelector::parse(query.get("labelSelector").unwrap_or_default())
+            .map_err(Failure::bad_request)?,
+        fields: FieldSelector::parse(query.get("fieldSelector").unwrap_or_default())
+            .map_err(Failure::bad_request)?,
+    })
+}
+
+fn parse(body: &[u8]) -> store::Result<Value> {
+    serde_json::from_slice(body).map_err(|e| Failure::bad_request(e.to_string()))
+}
+
+fn namespaced_name(obj: &Value) -> (String, String) {
+    let field = |name| obj["metadata"][name].as_str().unwrap_or_default().to_owned();
+    (field("namespace"), field("name"))
+}
+
+fn list_response(store: &Store, resource: &Resource, items: Vec<Value>) -> Value {
+    json!({
+        "apiVersion": resource.api_version(),
+        "kind": format!("{}List", store.kind(resource).unwrap_or_default()),
+        "metadata": { "resourceVersion": store.resource_version().to_string() },
+        "items": items,
+    })
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body).expect("serializing JSON")))
+        .expect("valid response")
+}
+
+fn watch_event(event: &str, object: Value) -> Value {
+    json!({ "type": event, "object": object })
+}
+
+// Turns a change into the event seen by a watch with `selector`.
+// Objects changed to no longer match are seen as deleted, and objects changed to match as added.
+fn change_event(resource: &Resource, selector: &Selector, change: &Change) -> Option<Value> {
+    if &change.resource != resource {
+        return None;
+    }
+    let matches = |obj: &Value| selector.matches(&change.namespace, obj);
+    let event = match (change.old.as_ref().map(matches), matches(&change.object)) {
+        (Some(true), true) | (None, true) => change.event,
+        (Some(false), true) => "ADDED",
+        (Some(true), false) => "DELETED",
+        (_, false) => return None,
+    };
+    Some(watch_event(event, change.object.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{pin_mut, TryStreamExt};
+    use k8s_openapi::api::core::v1::{ConfigMap, Pod};
+    use kube_core::WatchEvent;
+
+    use crate::{
+        api::{Api, DeleteParams, ListParams, Patch, PatchParams, PostParams},
+        Error,
+    };
+
+    fn config_map(name: &str, labels: Value) -> ConfigMap {
+        serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": name, "labels": labels },
+            "data": { "key": "value" },
+        }))
+        .unwrap()
+    }
+
+    fn api_error_code<T: std::fmt::Debug>(res: crate::Result<T>) -> u16 {
+        match res {
+            Err(Error::Api(err)) => err.code,
+            res => panic!("expected an API error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn parses_paths() {
+        let target = Target::parse("/apis/apps/v1/namespaces/ns/deployments/web/status").unwrap();
+        assert_eq!(target.resource.api_version(), "apps/v1");
+        assert_eq!(target.resource.plural, "deployments");
+        assert_eq!(target.namespace.as_deref(), Some("ns"));
+        assert_eq!(target.name.as_deref(), Some("web"));
+        assert_eq!(target.subresource.as_deref(), Some("status"));
+
+        let target = Target::parse("/api/v1/namespaces/ns/status").unwrap();
+        assert_eq!(target.resource.plural, "namespaces");
+        assert_eq!(target.namespace, None);
+        assert_eq!(target.subresource.as_deref(), Some("status"));
+
+        assert_eq!(Target::parse("/api/v1/pods").unwrap().name, None);
+        assert_eq!(Target::parse("/version"), None);
+    }
+
+    #[tokio::test]
+    async fn create_get_replace() {
+        let cms: Api<ConfigMap> = Api::default_namespaced(FakeApiServer::new().client());
+        let pp = PostParams::default();
+        let created = cms.create(&pp, &config_map("a", json!({}))).await.unwrap();
+        assert_eq!(created.metadata.namespace.as_deref(), Some("default"));
+        assert_eq!(created.metadata.resource_version.as_deref(), Some("1"));
+        assert!(created.metadata.uid.is_some() && created.metadata.creation_timestamp.is_some());
+        assert_eq!(
+            api_error_code(cms.create(&pp, &config_map("a", json!({}))).await),
+            409
+        );
+
+        let mut cm = cms.get("a").await.unwrap();
+        cm.data.insert("key".into(), "changed".into());
+        let replaced = cms.replace("a", &pp, &cm).await.unwrap();
+        assert_eq!(replaced.metadata.resource_version.as_deref(), Some("2"));
+        // `cm` is now stale
+        assert_eq!(api_error_code(cms.replace("a", &pp, &cm).await), 409);
+        assert_eq!(api_error_code(cms.get("b").await), 404);
+
+        let dry_run = PostParams {
+            dry_run: true,
+            ..PostParams::default()
+        };
+        cms.create(&dry_run, &config_map("b", json!({}))).await.unwrap();
+        assert_eq!(api_error_code(cms.get("b").await), 404);
+    }
+
+    #[tokio::test]
+    async fn list_with_selectors() {
+        let client = FakeApiServer::new().client();
+        let default: Api<ConfigMap> = Api::default_namespaced(client.clone());
+        let other: Api<ConfigMap> = Api::namespaced(client.clone(), "other");
+        let pp = PostParams::default();
+        default
+            .create(&pp, &config_map("a", json!({ "app": "web" })))
+            .await
+            .unwrap();
+        default
+            .create(&pp, &config_map("b", json!({ "app": "db" })))
+            .await
+            .unwrap();
+        other
+            .create(&pp, &config_map("c", json!({ "app": "web" })))
+            .await
+            .unwrap();
+
+        let names = |list: crate::api::ObjectList<ConfigMap>| {
+            list.items
+                .into_iter()
+                .map(|cm| cm.metadata.name.unwrap())
+                .collect::<Vec<_>>()
+        };
+        let list = default.list(&ListParams::default()).await.unwrap();
+        assert_eq!(list.metadata.resource_version.as_deref(), Some("3"));
+        assert_eq!(names(list), vec!["a", "b"]);
+        let all: Api<ConfigMap> = Api::all(client);
+        let web = ListParams::default().labels("app=web");
+        assert_eq!(names(all.list(&web).await.unwrap()), vec!["a", "c"]);
+        let not_a = ListParams::default().fields("metadata.name!=a");
+        assert_eq!(names(all.list(&not_a).await.unwrap()), vec!["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn patches() {
+        let cms: Api<ConfigMap> = Api::default_namespaced(FakeApiServer::new().client());
+        let pp = PatchParams::apply("test");
+        // Apply creates missing objects
+        cms.patch("a", &pp, &Patch::Apply(config_map("a", json!({}))))
+            .await
+            .unwrap();
+        let cm = cms
+            .patch("a", &pp, &Patch::Merge(json!({ "data": { "other": "x" } })))
+            .await
+            .unwrap();
+        assert_eq!(cm.data.len(), 2);
+
+        let patch = serde_json::from_value(json!([
+            { "op": "test", "path": "/data/key", "value": "value" },
+            { "op": "remove", "path": "/data/key" },
+        ]))
+        .unwrap();
+        let cm = cms.patch("a", &pp, &Patch::Json::<()>(patch)).await.unwrap();
+        assert_eq!(cm.data.keys().collect::<Vec<_>>(), vec!["other"]);
+
+        // Missing values test as null, as used by `kube_runtime::finalizer`
+        let add_finalizer = serde_json::from_value(json!([
+            { "op": "test", "path": "/metadata/finalizers", "value": null },
+            { "op": "add", "path": "/metadata/finalizers", "value": ["test"] },
+        ]))
+        .unwrap();
+        let cm = cms
+            .patch("a", &pp, &Patch::Json::<()>(add_finalizer))
+            .await
+            .unwrap();
+        assert_eq!(cm.metadata.finalizers, vec!["test"]);
+
+        let failing =
+            serde_json::from_value(json!([{ "op": "test", "path": "/data/key", "value": "value" }])).unwrap();
+        assert_eq!(
+            api_error_code(cms.patch("a", &pp, &Patch::Json::<()>(failing)).await),
+            422
+        );
+    }
+
+    #[tokio::test]
+    async fn status_subresource_and_generation() {
+        let pods: Api<Pod> = Api::default_namespaced(FakeApiServer::new().client());
+        let pod: Pod = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "p" },
+            "spec": { "containers": [{ "name": "c", "image": "a" }] },
+            "status": { "phase": "Running" },
+        }))
+        .unwrap();
+        let pod = pods.create(&PostParams::default(), &pod).await.unwrap();
+        assert_eq!(pod.status, None);
+        assert_eq!(pod.metadata.generation, Some(1));
+
+        let pp = PatchParams::default();
+        let status = Patch::Merge(json!({ "status": { "phase": "Running" } }));
+        let pod = pods.patch_status("p", &pp, &status).await.unwrap();
+        assert_eq!(pod.status.unwrap().phase.as_deref(), Some("Running"));
+        assert_eq!(pod.metadata.generation, Some(1));
+
+        // Status is ignored by writes to the object itself
+        let patch = Patch::Merge(json!({
+            "spec": { "containers": [{ "name": "c", "image": "b" }] },
+            "status": { "phase": "Failed" },
+        }));
+        let pod = pods.patch("p", &pp, &patch).await.unwrap();
+        assert_eq!(pod.status.unwrap().phase.as_deref(), Some("Running"));
+        assert_eq!(pod.metadata.generation, Some(2));
+        assert_eq!(pod.metadata.resource_version.as_deref(), Some("3"));
+
+        // Writes without changes do not bump the resource version
+        let pod = pods.patch("p", &pp, &patch).await.unwrap();
+        assert_eq!(pod.metadata.resource_version.as_deref(), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn finalizers_delay_deletion() {
+        let cms: Api<ConfigMap> = Api::default_namespaced(FakeApiServer::new().client());
+        let mut cm = config_map("a", json!({}));
+        cm.metadata.finalizers = vec!["test/cleanup".into()];
+        cms.create(&PostParams::default(), &cm).await.unwrap();
+
+        let deleting = cms.delete("a", &DeleteParams::default()).await.unwrap();
+        let deleting = deleting.left().expect("object being deleted");
+        assert!(deleting.metadata.deletion_timestamp.is_some());
+        assert!(cms.get("a").await.is_ok());
+
+        let patch = Patch::Merge(json!({ "metadata": { "finalizers": null } }));
+        cms.patch("a", &PatchParams::default(), &patch).await.unwrap();
+        assert_eq!(api_error_code(cms.get("a").await), 404);
+    }
+
+    #[tokio::test]
+    async fn watch_from_resource_version() {
+        let server = FakeApiServer::new();
+        let cms: Api<ConfigMap> = Api::default_namespaced(server.client());
+        let pp = PostParams::default();
+        cms.create(&pp, &config_map("a", json!({ "app": "web" })))
+            .await
+            .unwrap();
+        let rv = cms
+            .list(&ListParams::default())
+            .await
+            .unwrap()
+            .metadata
+            .resource_version
+            .unwrap();
+        cms.create(&pp, &config_map("b", json!({ "app": "web" })))
+            .await
+            .unwrap();
+
+        let lp = ListParams::default().labels("app=web");
+        let events = cms.watch(&lp, &rv).await.unwrap();
+        pin_mut!(events);
+        let name = |event: Option<WatchEvent<ConfigMap>>| match event {
+            Some(WatchEvent::Added(cm)) => format!("added {}", cm.metadata.name.unwrap()),
+            Some(WatchEvent::Modified(cm)) => format!("modified {}", cm.metadata.name.unwrap()),
+            Some(WatchEvent::Deleted(cm)) => format!("deleted {}", cm.metadata.name.unwrap()),
+            Some(WatchEvent::Bookmark(b)) => format!("bookmark {}", b.metadata.resource_version),
+            Some(WatchEvent::Error(e)) => format!("error {}", e.code),
+            None => "end".into(),
+        };
+        assert_eq!(name(events.try_next().await.unwrap()), "added b");
+
+        let pp = PatchParams::default();
+        let relabel = |app| Patch::Merge(json!({ "metadata": { "labels": { "app": app } } }));
+        cms.patch("a", &pp, &relabel("db")).await.unwrap();
+        assert_eq!(name(events.try_next().await.unwrap()), "deleted a");
+        cms.patch("a", &pp, &relabel("web")).await.unwrap();
+        assert_eq!(name(events.try_next().await.unwrap()), "added a");
+        server.bookmark();
+        assert_eq!(name(events.try_next().await.unwrap()), "bookmark 4");
+        cms.delete("b", &DeleteParams::default()).await.unwrap();
+        assert_eq!(name(events.try_next().await.unwrap()), "deleted b");
+
+        server.compact();
+        let events = cms.watch(&lp, &rv).await.unwrap();
+        pin_mut!(events);
+        assert_eq!(name(events.try_next().await.unwrap()), "error 410");
+        assert_eq!(name(events.try_next().await.unwrap()), "end");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watch_times_out() {
+        let cms: Api<ConfigMap> = Api::default_namespaced(FakeApiServer::new().client());
+        let events = cms.watch(&ListParams::default().timeout(10), "0").await.unwrap();
+        assert_eq!(events.try_collect::<Vec<_>>().await.unwrap().len(), 0);
+    }
+}
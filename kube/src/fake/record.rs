@@ -0,0 +1,515 @@
+//! Recording of interactions with an apiserver, and replaying them without one.
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use http::{HeaderMap, Request, Response, StatusCode};
+use hyper::Body;
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+use tower::{BoxError, Layer, Service};
+
+/// A request and the response it got, as stored in a fixture file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    /// The request
+    pub request: RecordedRequest,
+    /// The response
+    pub response: RecordedResponse,
+}
+
+/// A recorded request, without its headers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// HTTP method
+    pub method: String,
+    /// Path and query
+    pub uri: String,
+    /// Request body
+    #[serde(default, with = "body", skip_serializing_if = "Bytes::is_empty")]
+    pub body: Bytes,
+}
+
+impl RecordedRequest {
+    fn matches<B>(&self, req: &Request<B>) -> bool {
+        let (path, query) = self.uri.split_once('?').unwrap_or((&self.uri, ""));
+        self.method == req.method().as_str()
+            && path == req.uri().path()
+            && query_pairs(Some(query)) == query_pairs(req.uri().query())
+    }
+}
+
+/// A recorded response.
+///
+/// Streamed bodies, like the events of a watch, are recorded up to where the client stopped reading them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Response headers in the order they were received, including repeated ones like `Warning`
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// Response body
+    #[serde(default, with = "body")]
+    pub body: Bytes,
+}
+
+/// Layer that applies [`Record`] which records the interactions of a `Client`.
+///
+/// Services created by the same layer (and their clones) record to the same list of interactions,
+/// which can be written to a fixture file for [`Replay`] with [`RecordLayer::save`].
+///
+/// ```no_run
+/// # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+/// use kube::{client::ConfigExt, fake::RecordLayer, Client, Config};
+/// use tower::ServiceBuilder;
+///
+/// let config = Config::infer().await?;
+/// let recorder = RecordLayer::new();
+/// let service = ServiceBuilder::new()
+///     .layer(config.base_uri_layer())
+///     .layer(recorder.clone())
+///     .option_layer(config.auth_layer()?)
+///     .service(hyper::Client::new());
+/// let client = Client::new(service, config.default_namespace);
+/// // use the client
+/// recorder.save("tests/fixtures/pods.json")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RecordLayer {
+    // Responses are recorded when their bodies end, so slots keep the order of the requests
+    interactions: Arc<Mutex<Vec<Option<Interaction>>>>,
+}
+
+impl RecordLayer {
+    /// Create a layer with no recorded interactions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The interactions recorded so far, in the order of their requests.
+    pub fn interactions(&self) -> Vec<Interaction> {
+        let interactions = self.interactions.lock().expect("recording lock poisoned");
+        interactions.iter().flatten().cloned().collect()
+    }
+
+    /// Write the interactions recorded so far to a fixture file at `path`.
+    ///
+    /// Request headers are not recorded, so the fixture does not contain credentials.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, &self.interactions())?;
+        Ok(())
+    }
+}
+
+impl<S> Layer<S> for RecordLayer {
+    type Service = Record<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Record {
+            interactions: self.interactions.clone(),
+            inner,
+        }
+    }
+}
+
+/// Middleware that records requests and their responses.
+///
+/// Request bodies are buffered to record them.
+#[derive(Clone, Debug)]
+pub struct Record<S> {
+    interactions: Arc<Mutex<Vec<Option<Interaction>>>>,
+    inner: S,
+}
+
+impl<S, ResBody> Service<Request<Body>> for Record<S>
+where
+    S: Service<Request<Body>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ResBody: http_body::Body<Data = Bytes>,
+{
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+    type Response = Response<RecordBody<ResBody>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Pass on the service that was driven to readiness, like `RefreshToken`.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let interactions = self.interactions.clone();
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            let request = RecordedRequest {
+                method: parts.method.to_string(),
+                uri: parts
+                    .uri
+                    .path_and_query()
+                    .map_or_else(|| parts.uri.path().to_owned(), ToString::to_string),
+                body: body.clone(),
+            };
+            let index = {
+                let mut interactions = interactions.lock().expect("recording lock poisoned");
+                interactions.push(None);
+                interactions.len() - 1
+            };
+
+            let res = inner
+                .call(Request::from_parts(parts, Body::from(body)))
+                .await
+                .map_err(Into::into)?;
+            let response = RecordedResponse {
+                status: res.status().as_u16(),
+                headers: recorded_headers(res.headers()),
+                body: Bytes::new(),
+            };
+            let pending = Pending {
+                interactions,
+                index,
+                interaction: Interaction { request, response },
+                body: BytesMut::new(),
+            };
+            Ok(res.map(|inner| RecordBody {
+                inner,
+                pending: Some(pending),
+            }))
+        })
+    }
+}
+
+fn recorded_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect()
+}
+
+/// Response body of [`Record`], recording the data read from it.
+#[pin_project]
+#[derive(Debug)]
+pub struct RecordBody<B> {
+    #[pin]
+    inner: B,
+    pending: Option<Pending>,
+}
+
+impl<B> http_body::Body for RecordBody<B>
+where
+    B: http_body::Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let data = futures::ready!(this.inner.poll_data(cx));
+        match &data {
+            Some(Ok(chunk)) => {
+                if let Some(pending) = this.pending {
+                    pending.body.extend_from_slice(chunk);
+                }
+            }
+            // Recorded when dropped
+            _ => drop(this.pending.take()),
+        }
+        Poll::Ready(data)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// An interaction that is recorded once its response body ends or is dropped
+#[derive(Debug)]
+struct Pending {
+    interactions: Arc<Mutex<Vec<Option<Interaction>>>>,
+    index: usize,
+    interaction: Interaction,
+    body: BytesMut,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let mut interaction = self.interaction.clone();
+        interaction.response.body = self.body.split().freeze();
+        if let Ok(mut interactions) = self.interactions.lock() {
+            interactions[self.index] = Some(interaction);
+        }
+    }
+}
+
+/// A `Service` to use with [`Client::new`](crate::Client::new) that responds with recorded interactions.
+///
+/// Each request is answered with the first unused interaction with the same method, path and query parameters,
+/// in any order. Requests without one fail.
+///
+/// ```no_run
+/// # async fn doc() -> Result<(), Box<dyn std::error::Error>> {
+/// use k8s_openapi::api::core::v1::Pod;
+/// use kube::{api::Api, fake::Replay, Client};
+///
+/// let client = Client::new(Replay::load("tests/fixtures/pods.json")?, "default");
+/// let pods: Api<Pod> = Api::default_namespaced(client);
+/// pods.get("blog").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Replay {
+    interactions: Arc<Mutex<Vec<Option<Interaction>>>>,
+}
+
+impl Replay {
+    /// Respond with `interactions`.
+    pub fn new(interactions: Vec<Interaction>) -> Self {
+        Self {
+            interactions: Arc::new(Mutex::new(interactions.into_iter().map(Some).collect())),
+        }
+    }
+
+    /// Respond with the interactions in the fixture file at `path`, as written by [`RecordLayer::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(Self::new(serde_json::from_reader(file)?))
+    }
+
+    /// The number of interactions that were not used yet.
+    pub fn remaining(&self) -> usize {
+        let interactions = self.interactions.lock().expect("replay lock poisoned");
+        interactions.iter().flatten().count()
+    }
+
+    fn respond<B>(&self, req: &Request<B>) -> Result<Response<Body>, BoxError> {
+        let mut interactions = self.interactions.lock().expect("replay lock poisoned");
+        let interaction = interactions
+            .iter_mut()
+            .find(|i| matches!(i, Some(i) if i.request.matches(req)))
+            .and_then(Option::take)
+            .ok_or_else(|| format!("no recorded interaction for {} {}", req.method(), req.uri()))?;
+
+        let recorded = interaction.response;
+        let mut res = Response::builder().status(StatusCode::from_u16(recorded.status)?);
+        for (name, value) in &recorded.headers {
+            res = res.header(name.as_str(), value.as_str());
+        }
+        Ok(res.body(Body::from(recorded.body))?)
+    }
+}
+
+fn query_pairs(query: Option<&str>) -> Vec<(String, String)> {
+    let mut pairs = form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs
+}
+
+impl Service<Request<Body>> for Replay {
+    type Error = BoxError;
+    type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+    type Response = Response<Body>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        futures::future::ready(self.respond(&req))
+    }
+}
+
+// Bodies are stored as text, or as base64 if they are not UTF-8 (like protobuf)
+mod body {
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Text(String),
+        Binary { base64: String },
+    }
+
+    pub fn serialize<S: Serializer>(body: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(body) {
+            Ok(text) => Repr::Text(text.to_owned()),
+            Err(_) => Repr::Binary {
+                base64: base64::encode(body),
+            },
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => Ok(Bytes::from(text)),
+            Repr::Binary { base64 } => base64::decode(base64)
+                .map(Bytes::from)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{StreamExt, TryStreamExt};
+    use k8s_openapi::api::core::v1::ConfigMap;
+    use serde_json::json;
+    use tower::ServiceBuilder;
+
+    use crate::{
+        api::{Api, ListParams, PostParams, WatchEvent},
+        fake::FakeApiServer,
+        Client, Error,
+    };
+
+    // Creates a config map, lists them and watches for the first event
+    async fn exercise(client: Client) -> (String, Vec<String>, String) {
+        let cms: Api<ConfigMap> = Api::default_namespaced(client);
+        let cm = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "a" },
+        }))
+        .unwrap();
+        let created = cms.create(&PostParams::default(), &cm).await.unwrap();
+        let list = cms.list(&ListParams::default()).await.unwrap();
+        let names = list.iter().map(|cm| cm.metadata.name.clone().unwrap()).collect();
+        let mut events = cms.watch(&ListParams::default(), "0").await.unwrap().boxed();
+        let first = match events.try_next().await.unwrap() {
+            Some(WatchEvent::Added(cm)) => cm.metadata.name.unwrap(),
+            other => panic!("unexpected event {:?}", other),
+        };
+        (created.metadata.uid.unwrap(), names, first)
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_interactions() {
+        let recorder = RecordLayer::new();
+        let service = ServiceBuilder::new()
+            .layer(recorder.clone())
+            .service(FakeApiServer::new());
+        let recorded = exercise(Client::new(service, "default")).await;
+
+        let interactions = recorder.interactions();
+        assert_eq!(interactions.len(), 3);
+        assert_eq!(interactions[0].request.method, "POST");
+        assert_eq!(interactions[0].response.status, 201);
+        // The watch never ended, so only what was read from it is recorded
+        assert!(interactions[2].request.uri.contains("watch=true"));
+        assert_eq!(
+            std::str::from_utf8(&interactions[2].response.body)
+                .unwrap()
+                .lines()
+                .count(),
+            1
+        );
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        recorder.save(file.path()).unwrap();
+        let replay = Replay::load(file.path()).unwrap();
+        let replayed = exercise(Client::new(replay.clone(), "default")).await;
+        assert_eq!(replayed, recorded);
+        assert_eq!(replay.remaining(), 0);
+
+        // Interactions are used once
+        let cms: Api<ConfigMap> = Api::default_namespaced(Client::new(replay, "default"));
+        match cms.list(&ListParams::default()).await {
+            Err(Error::Service(err)) => {
+                assert!(err.to_string().starts_with("no recorded interaction for GET"))
+            }
+            res => panic!("expected replay to fail, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn matches_query_in_any_order() {
+        let replay = Replay::new(vec![Interaction {
+            request: RecordedRequest {
+                method: "GET".into(),
+                uri: "/api/v1/pods?labelSelector=app%3Dweb&limit=1".into(),
+                body: Bytes::new(),
+            },
+            response: RecordedResponse {
+                status: 200,
+                headers: Vec::new(),
+                body: Bytes::from_static(b"{}"),
+            },
+        }]);
+        let req = |uri| Request::get(uri).body(()).unwrap();
+        assert!(replay.respond(&req("/api/v1/pods?limit=2")).is_err());
+        assert!(replay
+            .respond(&req("/api/v1/pods?limit=1&labelSelector=app=web"))
+            .is_ok());
+    }
+
+    #[test]
+    fn repeated_headers_are_kept() {
+        let mut headers = HeaderMap::new();
+        headers.append("warning", "299 - \"first\"".parse().unwrap());
+        headers.append("warning", "299 - \"second\"".parse().unwrap());
+        let recorded = recorded_headers(&headers);
+        assert_eq!(recorded.len(), 2);
+
+        let replay = Replay::new(vec![Interaction {
+            request: RecordedRequest {
+                method: "GET".into(),
+                uri: "/api/v1/pods".into(),
+                body: Bytes::new(),
+            },
+            response: RecordedResponse {
+                status: 200,
+                headers: recorded,
+                body: Bytes::from_static(b"{}"),
+            },
+        }]);
+        let res = replay
+            .respond(&Request::get("/api/v1/pods").body(()).unwrap())
+            .unwrap();
+        let warnings = res.headers().get_all("warning").iter().collect::<Vec<_>>();
+        assert_eq!(warnings, vec!["299 - \"first\"", "299 - \"second\""]);
+    }
+
+    #[test]
+    fn binary_bodies_roundtrip() {
+        let response = RecordedResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: Bytes::from_static(b"k8s\0\xff"),
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["body"], json!({ "base64": "azhzAP8=" }));
+        assert_eq!(
+            serde_json::from_value::<RecordedResponse>(json).unwrap(),
+            response
+        );
+    }
+}
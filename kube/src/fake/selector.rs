@@ -0,0 +1,168 @@
+//! Label and field selectors for filtering objects.
+use serde_json::Value;
+
+/// A parsed `labelSelector` query parameter.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct LabelSelector(Vec<Requirement>);
+
+#[derive(Debug, PartialEq)]
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+    Exists(String),
+    DoesNotExist(String),
+}
+
+impl LabelSelector {
+    pub(super) fn parse(selector: &str) -> Result<Self, String> {
+        split_terms(selector)
+            .into_iter()
+            .map(|term| parse_requirement(term).ok_or_else(|| format!("invalid label selector: {:?}", term)))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub(super) fn matches(&self, obj: &Value) -> bool {
+        let labels = obj.pointer("/metadata/labels");
+        let label = |key: &str| labels.and_then(|l| l.get(key)).and_then(Value::as_str);
+        self.0.iter().all(|req| match req {
+            Requirement::Equals(key, value) => label(key) == Some(value),
+            Requirement::NotEquals(key, value) => label(key) != Some(value),
+            Requirement::In(key, values) => values.iter().any(|v| label(key) == Some(v)),
+            Requirement::NotIn(key, values) => values.iter().all(|v| label(key) != Some(v)),
+            Requirement::Exists(key) => label(key).is_some(),
+            Requirement::DoesNotExist(key) => label(key).is_none(),
+        })
+    }
+}
+
+// Split on commas outside of the parentheses of set based requirements
+fn split_terms(selector: &str) -> Vec<&str> {
+    let mut terms = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in selector.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                terms.push(&selector[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    terms.push(&selector[start..]);
+    terms
+        .into_iter()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+fn parse_requirement(term: &str) -> Option<Requirement> {
+    if let Some((key, value)) = term.split_once("!=") {
+        return Some(Requirement::NotEquals(key.trim().into(), value.trim().into()));
+    }
+    if let Some((key, value)) = term.split_once("==").or_else(|| term.split_once('=')) {
+        return Some(Requirement::Equals(key.trim().into(), value.trim().into()));
+    }
+    if let Some(key) = term.strip_prefix('!') {
+        return Some(Requirement::DoesNotExist(key.trim().into()));
+    }
+    let mut words = term.splitn(2, char::is_whitespace);
+    let key = words.next()?.to_owned();
+    let rest = match words.next() {
+        Some(rest) => rest.trim_start(),
+        None => return Some(Requirement::Exists(key)),
+    };
+    let (op, values) = rest.split_once('(')?;
+    let values = values
+        .strip_suffix(')')?
+        .split(',')
+        .map(|v| v.trim().to_owned())
+        .collect();
+    match op.trim() {
+        "in" => Some(Requirement::In(key, values)),
+        "notin" => Some(Requirement::NotIn(key, values)),
+        _ => None,
+    }
+}
+
+/// A parsed `fieldSelector` query parameter.
+///
+/// Any field can be selected on by its dotted path, like `metadata.name` or `status.phase`.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct FieldSelector(Vec<(String, bool, String)>);
+
+impl FieldSelector {
+    pub(super) fn parse(selector: &str) -> Result<Self, String> {
+        split_terms(selector)
+            .into_iter()
+            .map(|term| {
+                let (path, equal, value) = if let Some((path, value)) = term.split_once("!=") {
+                    (path, false, value)
+                } else if let Some((path, value)) = term.split_once("==").or_else(|| term.split_once('=')) {
+                    (path, true, value)
+                } else {
+                    return Err(format!("invalid field selector: {:?}", term));
+                };
+                Ok((
+                    format!("/{}", path.trim().replace('.', "/")),
+                    equal,
+                    value.trim().into(),
+                ))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub(super) fn matches(&self, obj: &Value) -> bool {
+        self.0.iter().all(|(pointer, equal, value)| {
+            // Missing fields match the empty string, like in the apiserver
+            let field = match obj.pointer(pointer) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+            };
+            (&field == value) == *equal
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn labeled(labels: Value) -> Value {
+        json!({ "metadata": { "name": "a", "labels": labels }, "status": { "phase": "Running" } })
+    }
+
+    #[test]
+    fn label_selectors() {
+        let obj = labeled(json!({ "app": "web", "tier": "frontend" }));
+        let matches = |selector: &str| LabelSelector::parse(selector).unwrap().matches(&obj);
+        assert!(matches(""));
+        assert!(matches("app=web"));
+        assert!(matches("app==web,tier!=backend"));
+        assert!(matches("app in (web, api),env notin (prod)"));
+        assert!(matches("tier,!env"));
+        assert!(!matches("app=api"));
+        assert!(!matches("app notin (web)"));
+        assert!(!matches("env"));
+        assert!(LabelSelector::parse("app between (a,b)").is_err());
+    }
+
+    #[test]
+    fn field_selectors() {
+        let obj = labeled(json!({}));
+        let matches = |selector: &str| FieldSelector::parse(selector).unwrap().matches(&obj);
+        assert!(matches("metadata.name=a,status.phase==Running"));
+        assert!(matches("metadata.namespace="));
+        assert!(matches("spec.nodeName!=node-1"));
+        assert!(!matches("metadata.name!=a"));
+        assert!(FieldSelector::parse("metadata.name").is_err());
+    }
+}
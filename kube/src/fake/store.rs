@@ -0,0 +1,460 @@
+//! Object storage and write semantics of the fake apiserver.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::{SecondsFormat, Utc};
+use http::StatusCode;
+use serde_json::{json, Map, Value};
+use tokio::sync::broadcast;
+
+use super::selector::{FieldSelector, LabelSelector};
+
+/// Group, version and plural name of a resource.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(super) struct Resource {
+    pub(super) group: String,
+    pub(super) version: String,
+    pub(super) plural: String,
+}
+
+impl Resource {
+    pub(super) fn api_version(&self) -> String {
+        if self.group.is_empty() {
+            self.version.clone()
+        } else {
+            format!("{}/{}", self.group, self.version)
+        }
+    }
+}
+
+// Resource, namespace (empty if cluster scoped) and name
+type Key = (Resource, String, String);
+
+/// An error returned to the client as a `Status`.
+#[derive(Debug)]
+pub(super) struct Failure {
+    pub(super) code: StatusCode,
+    pub(super) reason: &'static str,
+    pub(super) message: String,
+}
+
+impl Failure {
+    pub(super) fn new(code: StatusCode, reason: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            reason,
+            message: message.into(),
+        }
+    }
+
+    pub(super) fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "BadRequest", message)
+    }
+
+    fn not_found(resource: &Resource, name: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NotFound",
+            format!("{} {:?} not found", resource.plural, name),
+        )
+    }
+
+    fn conflict(resource: &Resource, name: &str) -> Self {
+        Self::new(
+            StatusCode::CONFLICT,
+            "Conflict",
+            format!(
+                "Operation cannot be fulfilled on {} {:?}: the object has been modified; please apply your changes to the latest version and try again",
+                resource.plural, name
+            ),
+        )
+    }
+
+    pub(super) fn gone(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::GONE, "Expired", message)
+    }
+
+    pub(super) fn to_status(&self) -> Value {
+        json!({
+            "apiVersion": "v1",
+            "kind": "Status",
+            "metadata": {},
+            "status": "Failure",
+            "message": self.message,
+            "reason": self.reason,
+            "code": self.code.as_u16(),
+        })
+    }
+}
+
+pub(super) type Result<T> = std::result::Result<T, Failure>;
+
+/// A change to an object, kept in the history for watches.
+#[derive(Clone, Debug)]
+pub(super) struct Change {
+    pub(super) resource: Resource,
+    pub(super) namespace: String,
+    pub(super) resource_version: u64,
+    pub(super) event: &'static str,
+    pub(super) object: Value,
+    /// The object before a `MODIFIED` change
+    pub(super) old: Option<Value>,
+}
+
+/// What is sent to running watches.
+#[derive(Clone, Debug)]
+pub(super) enum Notification {
+    Change(Box<Change>),
+    Bookmark(u64),
+}
+
+/// Filters objects for lists and watches.
+#[derive(Debug, Default)]
+pub(super) struct Selector {
+    pub(super) namespace: Option<String>,
+    pub(super) labels: LabelSelector,
+    pub(super) fields: FieldSelector,
+}
+
+impl Selector {
+    pub(super) fn matches(&self, namespace: &str, obj: &Value) -> bool {
+        self.namespace.iter().all(|ns| ns == namespace)
+            && self.labels.matches(obj)
+            && self.fields.matches(obj)
+    }
+}
+
+/// Where a write goes: the object or its status subresource.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Part {
+    Main,
+    Status,
+}
+
+pub(super) struct Store {
+    objects: BTreeMap<Key, Value>,
+    resource_version: u64,
+    history: VecDeque<Change>,
+    history_limit: usize,
+    // Highest resource version dropped from the history
+    compacted: u64,
+    kinds: HashMap<Resource, String>,
+    notifications: broadcast::Sender<Notification>,
+}
+
+impl Store {
+    pub(super) fn new(history_limit: usize) -> Self {
+        Self {
+            objects: BTreeMap::new(),
+            resource_version: 0,
+            history: VecDeque::new(),
+            history_limit,
+            compacted: 0,
+            kinds: HashMap::new(),
+            notifications: broadcast::channel(1024).0,
+        }
+    }
+
+    pub(super) fn resource_version(&self) -> u64 {
+        self.resource_version
+    }
+
+    /// Kind of the objects of `resource`, if any were written.
+    pub(super) fn kind(&self, resource: &Resource) -> Option<&str> {
+        self.kinds.get(resource).map(String::as_str)
+    }
+
+    pub(super) fn get(&self, resource: &Resource, namespace: &str, name: &str) -> Result<Value> {
+        self.objects
+            .get(&(resource.clone(), namespace.into(), name.into()))
+            .cloned()
+            .ok_or_else(|| Failure::not_found(resource, name))
+    }
+
+    pub(super) fn list(&self, resource: &Resource, selector: &Selector) -> Vec<Value> {
+        self.objects
+            .iter()
+            .filter(|((r, ns, _), obj)| r == resource && selector.matches(ns, obj))
+            .map(|(_, obj)| obj.clone())
+            .collect()
+    }
+
+    pub(super) fn create(
+        &mut self,
+        resource: &Resource,
+        namespace: &str,
+        mut obj: Value,
+        dry_run: bool,
+    ) -> Result<Value> {
+        let meta = metadata_mut(&mut obj)?;
+        match meta.get("namespace").and_then(Value::as_str) {
+            Some(ns) if !ns.is_empty() && ns != namespace => {
+                return Err(Failure::bad_request(
+                    "the namespace of the provided object does not match the namespace sent on the request",
+                ));
+            }
+            _ if !namespace.is_empty() => {
+                meta.insert("namespace".into(), namespace.into());
+            }
+            _ => {}
+        }
+        let name = match (meta.get("name"), meta.get("generateName")) {
+            (Some(Value::String(name)), _) if !name.is_empty() => name.clone(),
+            (_, Some(Value::String(prefix))) if !prefix.is_empty() => {
+                let name = format!("{}{:05x}", prefix, self.resource_version + 1);
+                meta.insert("name".into(), name.clone().into());
+                name
+            }
+            _ => {
+                return Err(Failure::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Invalid",
+                    "name or generateName is required",
+                ));
+            }
+        };
+        let key = (resource.clone(), namespace.to_owned(), name.clone());
+        if self.objects.contains_key(&key) {
+            return Err(Failure::new(
+                StatusCode::CONFLICT,
+                "AlreadyExists",
+                format!("{} {:?} already exists", resource.plural, name),
+            ));
+        }
+
+        meta.insert(
+            "uid".into(),
+            format!("00000000-0000-0000-0000-{:012x}", self.resource_version + 1).into(),
+        );
+        meta.insert(
+            "creationTimestamp".into(),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true).into(),
+        );
+        meta.insert("generation".into(), 1.into());
+        meta.remove("deletionTimestamp");
+        // Status can only be written through the status subresource
+        if let Some(obj) = obj.as_object_mut() {
+            obj.remove("status");
+        }
+        Ok(self.commit(key, "ADDED", obj, None, dry_run))
+    }
+
+    /// Replace the object or its status with `new`, unless it was changed since `new` was read.
+    pub(super) fn update(
+        &mut self,
+        resource: &Resource,
+        namespace: &str,
+        name: &str,
+        part: Part,
+        new: Value,
+        dry_run: bool,
+    ) -> Result<Value> {
+        let key = (resource.clone(), namespace.to_owned(), name.to_owned());
+        let old = self
+            .objects
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| Failure::not_found(resource, name))?;
+        match new.pointer("/metadata/name").and_then(Value::as_str) {
+            Some(n) if n != name => {
+                return Err(Failure::bad_request(
+                    "the name of the object does not match the name on the URL",
+                ))
+            }
+            _ => {}
+        }
+        match new.pointer("/metadata/resourceVersion").and_then(Value::as_str) {
+            Some(rv) if !rv.is_empty() && Some(rv) != resource_version(&old) => {
+                return Err(Failure::conflict(resource, name));
+            }
+            _ => {}
+        }
+
+        let mut obj = match part {
+            Part::Status => {
+                let mut obj = old.clone();
+                set_field(&mut obj, "status", new.get("status").cloned());
+                obj
+            }
+            Part::Main => {
+                let mut obj = new;
+                set_field(&mut obj, "status", old.get("status").cloned());
+                let generation = old.pointer("/metadata/generation").and_then(Value::as_i64);
+                let generation = generation.unwrap_or(1) + i64::from(spec_changed(&old, &obj));
+                let meta = metadata_mut(&mut obj)?;
+                for field in &[
+                    "name",
+                    "namespace",
+                    "uid",
+                    "creationTimestamp",
+                    "deletionTimestamp",
+                ] {
+                    match old["metadata"].get(*field) {
+                        Some(value) => meta.insert((*field).into(), value.clone()),
+                        None => meta.remove(*field),
+                    };
+                }
+                meta.insert("generation".into(), generation.into());
+                obj
+            }
+        };
+        set_field(
+            &mut obj["metadata"],
+            "resourceVersion",
+            old["metadata"].get("resourceVersion").cloned(),
+        );
+        if obj == old {
+            return Ok(old);
+        }
+
+        // Removing the last finalizer of an object that is being deleted deletes it
+        let finalized = obj.pointer("/metadata/deletionTimestamp").is_some() && !has_finalizers(&obj);
+        let event = if finalized { "DELETED" } else { "MODIFIED" };
+        Ok(self.commit(key, event, obj, Some(old), dry_run))
+    }
+
+    /// Delete the object, or mark it as being deleted if it has finalizers.
+    pub(super) fn delete(
+        &mut self,
+        resource: &Resource,
+        namespace: &str,
+        name: &str,
+        preconditions: &Value,
+        dry_run: bool,
+    ) -> Result<Value> {
+        let key = (resource.clone(), namespace.to_owned(), name.to_owned());
+        let old = self
+            .objects
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| Failure::not_found(resource, name))?;
+        for field in &["uid", "resourceVersion"] {
+            match preconditions.get(*field).and_then(Value::as_str) {
+                Some(expected) if Some(expected) != old["metadata"].get(*field).and_then(Value::as_str) => {
+                    return Err(Failure::conflict(resource, name));
+                }
+                _ => {}
+            }
+        }
+
+        if !has_finalizers(&old) {
+            return Ok(self.commit(key, "DELETED", old, None, dry_run));
+        }
+        if old.pointer("/metadata/deletionTimestamp").is_some() {
+            return Ok(old);
+        }
+        let mut obj = old.clone();
+        obj["metadata"]["deletionTimestamp"] = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true).into();
+        Ok(self.commit(key, "MODIFIED", obj, Some(old), dry_run))
+    }
+
+    // Stores or removes the object with a new resource version and notifies watches
+    fn commit(
+        &mut self,
+        key: Key,
+        event: &'static str,
+        mut obj: Value,
+        old: Option<Value>,
+        dry_run: bool,
+    ) -> Value {
+        if dry_run {
+            return obj;
+        }
+        self.resource_version += 1;
+        obj["metadata"]["resourceVersion"] = self.resource_version.to_string().into();
+        if let Some(kind) = obj.get("kind").and_then(Value::as_str) {
+            self.kinds.insert(key.0.clone(), kind.to_owned());
+        }
+        let change = Change {
+            resource: key.0.clone(),
+            namespace: key.1.clone(),
+            resource_version: self.resource_version,
+            event,
+            object: obj.clone(),
+            old,
+        };
+        if event == "DELETED" {
+            self.objects.remove(&key);
+        } else {
+            self.objects.insert(key, obj.clone());
+        }
+        self.history.push_back(change.clone());
+        while self.history.len() > self.history_limit {
+            if let Some(dropped) = self.history.pop_front() {
+                self.compacted = dropped.resource_version;
+            }
+        }
+        // Fails only when there are no watches
+        let _ = self.notifications.send(Notification::Change(Box::new(change)));
+        obj
+    }
+
+    /// Changes after `resource_version`, or an error if some of them were dropped from the history.
+    pub(super) fn changes_since(&self, resource_version: u64) -> Result<impl Iterator<Item = &Change>> {
+        if resource_version < self.compacted {
+            return Err(Failure::gone(format!(
+                "too old resource version: {} ({})",
+                resource_version,
+                self.compacted + 1
+            )));
+        }
+        Ok(self
+            .history
+            .iter()
+            .filter(move |c| c.resource_version > resource_version))
+    }
+
+    pub(super) fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.notifications.subscribe()
+    }
+
+    pub(super) fn bookmark(&self) {
+        let _ = self
+            .notifications
+            .send(Notification::Bookmark(self.resource_version));
+    }
+
+    pub(super) fn compact(&mut self) {
+        self.history.clear();
+        self.compacted = self.resource_version;
+    }
+}
+
+fn metadata_mut(obj: &mut Value) -> Result<&mut Map<String, Value>> {
+    let obj = obj
+        .as_object_mut()
+        .ok_or_else(|| Failure::bad_request("expected an object"))?;
+    obj.entry("metadata")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .ok_or_else(|| Failure::bad_request("expected metadata to be an object"))
+}
+
+fn set_field(obj: &mut Value, field: &str, value: Option<Value>) {
+    if let Some(obj) = obj.as_object_mut() {
+        match value {
+            Some(value) => obj.insert(field.into(), value),
+            None => obj.remove(field),
+        };
+    }
+}
+
+fn resource_version(obj: &Value) -> Option<&str> {
+    obj.pointer("/metadata/resourceVersion").and_then(Value::as_str)
+}
+
+fn has_finalizers(obj: &Value) -> bool {
+    matches!(obj.pointer("/metadata/finalizers"), Some(Value::Array(f)) if !f.is_empty())
+}
+
+// The generation is bumped for changes outside of metadata and status
+fn spec_changed(old: &Value, new: &Value) -> bool {
+    fn spec(obj: &Value) -> Option<Vec<(&String, &Value)>> {
+        let obj = obj.as_object()?;
+        Some(
+            obj.iter()
+                .filter(|(k, _)| !matches!(k.as_str(), "metadata" | "status"))
+                .collect(),
+        )
+    }
+    spec(old) != spec(new)
+}
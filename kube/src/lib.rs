@@ -116,6 +116,10 @@ cfg_client! {
     pub use discovery::Discovery;
 }
 
+#[cfg(feature = "fake")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fake")))]
+pub mod fake;
+
 cfg_config! {
     pub mod config;
     #[doc(inline)]